@@ -0,0 +1,48 @@
+// GateServer and GateControl in one binary: role of the [Gate] section of cfg.toml chooses at
+// startup which of them runs, server - the gate automation box, control - the car.
+// Each role reads its own cfg.toml section and takes only the pins of its own board module.
+#[toml_cfg::toml_config]
+pub struct Config {
+    // server or control
+    #[default("server")]
+    role: &'static str,
+}
+
+#[derive(Clone, Copy)]
+enum Role {
+    Server,
+    Control,
+}
+
+// Role is checked when it is compiled in, so a misspelled one fails the build
+// instead of starting the other firmware
+const ROLE: Role = if same(CONFIG.role, "server") {
+    Role::Server
+} else if same(CONFIG.role, "control") {
+    Role::Control
+} else {
+    panic!("role in the [Gate] section of cfg.toml must be server or control")
+};
+
+// str comparison usable in const
+const fn same(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+fn main() -> anyhow::Result<()> {
+    match ROLE {
+        Role::Server => gate_server::run(),
+        Role::Control => gate_control::run(),
+    }
+}
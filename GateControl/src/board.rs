@@ -0,0 +1,29 @@
+// GateControl pin assignments (ESP32-C3-DevKitC-02 / ESP32-C3-DevKitM-1). Porting to another
+// board or ESP32 variant needs changes in this module only.
+//
+// Input, internal pull-up enabled:
+//   GPIO9 - SBS button to GND, active low (BOOT button on the DevKit). Also wakes up from light sleep
+// Output:
+//   GPIO8 - WS2812 RGB LED data, driven by RMT channel 0
+use esp_idf_hal::{gpio::*, peripheral::Peripheral, peripherals::Peripherals, rmt::CHANNEL0};
+use esp_idf_svc::sys::{gpio_num_t, gpio_num_t_GPIO_NUM_9};
+
+pub type SbsButtonPin = Gpio9;
+// Same pin as SbsButtonPin, for ESP-IDF sleep wakeup calls
+pub const SBS_BUTTON_GPIO_NUM: gpio_num_t = gpio_num_t_GPIO_NUM_9;
+pub type LedPin = Gpio8;
+pub type LedChannel = CHANNEL0;
+
+// Pins are taken unchecked, so each one has to be taken only once
+
+pub fn sbs_button_pin(peripherals: &mut Peripherals) -> SbsButtonPin {
+    unsafe { peripherals.pins.gpio9.clone_unchecked() }
+}
+
+pub fn led_pin(peripherals: &mut Peripherals) -> LedPin {
+    unsafe { peripherals.pins.gpio8.clone_unchecked() }
+}
+
+pub fn led_channel(peripherals: &mut Peripherals) -> LedChannel {
+    unsafe { peripherals.rmt.channel0.clone_unchecked() }
+}
@@ -0,0 +1,178 @@
+// Status LED animator. The LED shows a color solid or blinking, blinking is done by its own task,
+// so neither the poll loop nor blocking scans and requests have to keep it going.
+// A new pattern is shown at once by the caller, a repeated one keeps its blink phase.
+// identify() overrides the pattern with a rainbow for a while, patterns shown meanwhile
+// appear once it ends. Without a working LED start() is not called and all calls do nothing.
+use esp_idf_hal::delay::FreeRtos;
+use lazy_static::lazy_static;
+use log::error;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config,
+    rgb_led::{RGB8, WS2812RMT},
+};
+
+// Blink phase is checked this often
+const TICK_MS: u32 = 50;
+// Identify rainbow length and the period of one color cycle
+const IDENTIFY: Duration = Duration::from_secs(10);
+const RAINBOW_PERIOD_MS: u128 = 1500;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Blink {
+    Solid,
+    // 1 s period: scanning, provisioning, connection lost
+    Slow,
+    // 200 ms period: gate command in flight
+    Fast,
+}
+
+impl Blink {
+    // Half period, None - never off
+    fn half_period_ms(self) -> Option<u128> {
+        match self {
+            Blink::Solid => None,
+            Blink::Slow => Some(500),
+            Blink::Fast => Some(100),
+        }
+    }
+}
+
+struct Animator {
+    led: WS2812RMT<'static>,
+    color: RGB8,
+    blink: Blink,
+    since: Instant,
+    lit: bool,
+    // Rainbow is shown instead of the pattern until then
+    identify_until: Option<Instant>,
+}
+
+impl Animator {
+    fn write(&mut self, lit: bool) {
+        let color = if lit { self.color } else { RGB8::new(0, 0, 0) };
+        self.set_pixel(color);
+        self.lit = lit;
+    }
+
+    fn set_pixel(&mut self, color: RGB8) {
+        if let Err(e) = self.led.set_pixel(color) {
+            error!("Can not set LED color: {}", e);
+        }
+    }
+}
+
+lazy_static! {
+    // LED with the pattern shown, None - animator is not started
+    static ref ANIMATOR: Arc<Mutex<Option<Animator>>> = Arc::new(Mutex::new(None));
+}
+
+// Take over the LED and start the blink task, the LED is off until the first show()
+pub fn start(led: WS2812RMT<'static>) -> anyhow::Result<()> {
+    let mut animator = Animator {
+        led,
+        color: RGB8::new(0, 0, 0),
+        blink: Blink::Solid,
+        since: Instant::now(),
+        lit: false,
+        identify_until: None,
+    };
+    animator.write(false);
+    *ANIMATOR.clone().lock() = Some(animator);
+    std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(|| loop {
+            FreeRtos::delay_ms(TICK_MS);
+            tick();
+        })?;
+    Ok(())
+}
+
+// Show a status color designed at brightness 50. Blinking is solid with led_blink off
+pub fn show(color: RGB8, blink: Blink) {
+    let color = scaled(color);
+    let blink = if config().led_blink {
+        blink
+    } else {
+        Blink::Solid
+    };
+    let animator = ANIMATOR.clone();
+    let mut animator = animator.lock();
+    let Some(animator) = animator.as_mut() else {
+        return;
+    };
+    if animator.color == color && animator.blink == blink {
+        return;
+    }
+    animator.color = color;
+    animator.blink = blink;
+    animator.since = Instant::now();
+    if animator.identify_until.is_none() {
+        animator.write(true);
+    }
+}
+
+pub fn off() {
+    show(RGB8::new(0, 0, 0), Blink::Solid);
+}
+
+// Cycle through the rainbow for IDENTIFY to tell this unit from others, returns at once
+pub fn identify() {
+    if let Some(animator) = ANIMATOR.clone().lock().as_mut() {
+        animator.identify_until = Some(Instant::now() + IDENTIFY);
+    }
+}
+
+// Switch a blinking LED on or off by the time since its pattern was shown
+fn tick() {
+    let animator = ANIMATOR.clone();
+    let mut animator = animator.lock();
+    let Some(animator) = animator.as_mut() else {
+        return;
+    };
+    if let Some(identify_until) = animator.identify_until {
+        let now = Instant::now();
+        if now < identify_until {
+            let remaining_ms = (identify_until - now).as_millis();
+            let hue = (remaining_ms % RAINBOW_PERIOD_MS) * 6 * 50 / RAINBOW_PERIOD_MS;
+            animator.set_pixel(scaled(rainbow(hue as u32)));
+            return;
+        }
+        // Back to the pattern shown meanwhile
+        animator.identify_until = None;
+        animator.since = now;
+        animator.write(true);
+    }
+    let Some(half_period_ms) = animator.blink.half_period_ms() else {
+        return;
+    };
+    let lit = (animator.since.elapsed().as_millis() / half_period_ms) % 2 == 0;
+    if lit != animator.lit {
+        animator.write(lit);
+    }
+}
+
+// Fully saturated color at brightness 50 by hue 0..300: red, yellow, green, cyan, blue, violet
+fn rainbow(hue: u32) -> RGB8 {
+    let rise = (hue % 50) as u8;
+    let fall = 50 - rise;
+    match hue / 50 {
+        0 => RGB8::new(50, rise, 0),
+        1 => RGB8::new(fall, 50, 0),
+        2 => RGB8::new(0, 50, rise),
+        3 => RGB8::new(0, fall, 50),
+        4 => RGB8::new(rise, 0, 50),
+        _ => RGB8::new(50, 0, fall),
+    }
+}
+
+// Scale a status color designed at brightness 50 to led_brightness
+fn scaled(base: RGB8) -> RGB8 {
+    let scale = |c: u8| (c as u32 * config().led_brightness as u32 / 50).min(255) as u8;
+    RGB8::new(scale(base.r), scale(base.g), scale(base.b))
+}
@@ -0,0 +1,902 @@
+use anyhow::Context;
+use embedded_svc::{
+    http::client::{Client, Method},
+    io::Read,
+    utils::io,
+};
+use esp_idf_hal::{delay::FreeRtos, gpio::*, peripherals::Peripherals, reset};
+use esp_idf_svc::{
+    handle::RawHandle,
+    http::client::{Configuration as HttpConfiguration, EspHttpConnection},
+    mdns::EspMdns,
+    nvs::EspDefaultNvsPartition,
+    sys::{esp, esp_http_client_close, esp_tls_set_global_ca_store},
+};
+use gate_logic::reply::{parse_gate_status, parse_server_rssi};
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use rgb_led::{RGB8, WS2812RMT};
+use std::{
+    ffi::CString,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use crate::approach::{Departure, Dwell, PathLoss, RssiWindow, Threshold};
+use crate::gate_state::GateState;
+use crate::led::Blink;
+use crate::outcome::Outcome;
+use crate::urls::GATE_URLS;
+use crate::wifi::{connect_wifi, networks};
+
+pub use gate_logic::{approach, gate_state};
+
+pub mod board;
+pub mod led;
+pub mod outcome;
+pub mod power;
+pub mod provisioning;
+#[path = "../../common/rgb_led.rs"]
+pub mod rgb_led;
+pub mod settings;
+pub mod threshold_server;
+pub mod urls;
+pub mod validation;
+pub mod web;
+pub mod wifi;
+#[path = "../../common/wifi_setup.rs"]
+pub mod wifi_setup;
+
+// Scans for the access point after wake up in low power mode before sleeping again
+const SLEEP_MISSED_SCANS: u32 = 3;
+// RSSI range of the signal strength LED gradient
+const RSSI_WEAK: i8 = -90;
+const RSSI_STRONG: i8 = -50;
+
+// Peripherals, button pin and NVS partition, set by init_peripherals() at start
+pub struct Hardware {
+    /// Peripherals for drivers created later: WiFi modem
+    pub peripherals: Arc<Mutex<Peripherals>>,
+    /// Default NVS partition, shared by WiFi and settings storage
+    pub nvs_partition: EspDefaultNvsPartition,
+    /// Gate step-by-step (SBS) pin
+    /// When opened - then close, When closed - then open, in porgress - stop
+    pub gate_sbs: Arc<Mutex<PinDriver<'static, board::SbsButtonPin, Input>>>,
+}
+
+static HARDWARE: OnceLock<Hardware> = OnceLock::new();
+
+lazy_static::lazy_static! {
+    /// Time of the last auto-open, for auto_open_cooldown_secs
+    static ref LAST_AUTO_OPEN: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
+/// Take peripherals, set up the button pin, NVS and the LED, which is returned to the caller.
+/// The error tells which of them has failed. The LED is only feedback, so without it
+/// (None) the gate is still operated
+fn init_peripherals() -> anyhow::Result<Option<WS2812RMT<'static>>> {
+    let mut peripherals = Peripherals::take().context("Can not take peripherals")?;
+    let nvs_partition = EspDefaultNvsPartition::take().context("Can not take NVS partition")?;
+    let mut gate_sbs = PinDriver::input(board::sbs_button_pin(&mut peripherals))
+        .context("Can not set up SBS button pin")?;
+    gate_sbs
+        .set_pull(Pull::Up)
+        .context("Can not enable SBS button pull-up")?;
+    let led = match WS2812RMT::new(
+        board::led_pin(&mut peripherals),
+        board::led_channel(&mut peripherals),
+    ) {
+        Ok(led) => Some(led),
+        Err(e) => {
+            warn!(
+                "Can not set up RGB LED, continuing without LED feedback: {}",
+                e
+            );
+            None
+        }
+    };
+    let hardware = Hardware {
+        peripherals: Arc::new(Mutex::new(peripherals)),
+        nvs_partition,
+        gate_sbs: Arc::new(Mutex::new(gate_sbs)),
+    };
+    HARDWARE
+        .set(hardware)
+        .map_err(|_| anyhow::anyhow!("Peripherals are already initialized"))?;
+    Ok(led)
+}
+
+/// Hardware set up at start
+pub fn hardware() -> &'static Hardware {
+    HARDWARE
+        .get()
+        .expect("init_peripherals() is called first in main")
+}
+
+static VALID_CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Compiled config after `Config::validate()`, use it instead of `CONFIG`
+pub fn config() -> &'static Config {
+    VALID_CONFIG.get_or_init(|| CONFIG.validate())
+}
+
+// WiFi AP credentials
+#[toml_cfg::toml_config]
+pub struct Config {
+    // Comma separated lists, the strongest found access point is used
+    #[default("")]
+    wifi_ssid: &'static str,
+    #[default("")]
+    wifi_psk: &'static str,
+    // WiFi auth method: wpa2, wpa3, wpa2wpa3 or none, empty - none without password, else wpa2
+    #[default("")]
+    auth_method: &'static str,
+    // Reconnect to the last access point on its channel without scanning, scan if that fails
+    #[default(false)]
+    fast_connect: bool,
+    // Access points do not broadcast their SSID: connect by name without matching a scan
+    #[default(false)]
+    hidden_ssid: bool,
+    // Channel of the hidden access points, 0 - any channel
+    #[default(0)]
+    hidden_ssid_channel: u8,
+    #[default(-80)]
+    max_rssi: i8,
+    // RSSI to rise above after an auto-open before the next one is allowed
+    #[default(-70)]
+    min_rssi: i8,
+    // RSSI has to stay below max_rssi this long after connecting before auto-open, 0 - open at once
+    #[default(0)]
+    approach_dwell_ms: u32,
+    // RSSI samples averaged for the max_rssi comparison, 1 - each sample alone
+    #[default(1)]
+    rssi_window: u8,
+    // Close the gate when RSSI falls below this after the car was near, 0 - no close on departure
+    #[default(0)]
+    departure_rssi: i8,
+    // HTTP server while connected: GET/POST /threshold reads RSSI and sets max_rssi and min_rssi
+    #[default(false)]
+    threshold_server: bool,
+    // Heartbeat to GateServer /ping with the current RSSI while connected, 0 - no heartbeat
+    #[default(30)]
+    ping_secs: u32,
+    // Auto-open beyond this distance estimated from RSSI instead of max_rssi, 0 - use max_rssi
+    #[default(0.0)]
+    open_distance_m: f32,
+    // RSSI one meter away from the access point, for the distance estimation
+    #[default(-45)]
+    rssi_at_1m: i8,
+    // Path loss exponent of the distance estimation: 2 - open space, 2.7..4 - with obstacles
+    #[default(2.7)]
+    path_loss_exponent: f32,
+    // GateServer address like gate.local or https://gate.local, gate URLs are built from it.
+    // Empty - gate_*_url are used
+    #[default("")]
+    gate_host: &'static str,
+    // GateServer http_port, added to gate_host without a port of its own
+    #[default(80)]
+    http_port: u16,
+    #[default("http://192.168.0.1/gate_open")]
+    gate_open_url: &'static str,
+    #[default("http://192.168.0.1/gate_sbs")]
+    gate_sbs_url: &'static str,
+    #[default("http://192.168.0.1/gate_status")]
+    gate_status_url: &'static str,
+    #[default("http://192.168.0.1/gate_close")]
+    gate_close_url: &'static str,
+    // Button opens a closed gate and closes an opened one by its status, false - plain SBS toggle
+    #[default(true)]
+    smart_button: bool,
+    // Button held this long opens the gate by gate_open_url, 0 - no long press
+    #[default(0)]
+    long_press_ms: u32,
+    // How long to wait for the gate to report opened after auto-open
+    #[default(30)]
+    open_confirm_secs: u32,
+    // No auto-open within this time after the previous one, the button is not limited
+    #[default(0)]
+    auto_open_cooldown_secs: u32,
+    // Shared secret sent to GateServer in X-Gate-Token header
+    #[default("")]
+    gate_token: &'static str,
+    // GateServer certificate in PEM for https:// gate URLs, empty - HTTPS is not trusted
+    #[default("")]
+    gate_cert: &'static str,
+    // Static IP address, empty - use DHCP
+    #[default("")]
+    static_ip: &'static str,
+    #[default("")]
+    gateway: &'static str,
+    #[default("255.255.255.0")]
+    netmask: &'static str,
+    // GateServer request timeout
+    #[default(3000)]
+    http_timeout_ms: u32,
+    // Keep the connection to GateServer open between requests, false - a new one for each
+    #[default(true)]
+    http_keep_alive: bool,
+    // Attempts for each gate command
+    #[default(3)]
+    http_retries: u8,
+    // Longest delay between scans while the access point is not found, see scan_retry_delay_ms
+    #[default(4000)]
+    scan_backoff_max_ms: u32,
+    // Failed scan/connect attempts before reboot, 0 - retry forever
+    #[default(0)]
+    max_connect_attempts: u32,
+    // Failed scans before starting provisioning access point, 0 - never
+    #[default(60)]
+    provision_after_scans: u32,
+    #[default("GateControl-Setup")]
+    provision_ap_ssid: &'static str,
+    // Empty - open access point
+    #[default("gatecontrol")]
+    provision_ap_psk: &'static str,
+    // Provisioning access point lifetime before retrying the configured one
+    #[default(300)]
+    provision_timeout_secs: u32,
+    // SBS button held this long at power on erases settings stored in NVS, 0 - disabled
+    #[default(10)]
+    factory_reset_secs: u32,
+    // Low power mode: light sleep between checks, 0 - always awake
+    #[default(0)]
+    sleep_secs: u32,
+    // LED brightness 0..255, 50 - colors as designed, 0 - LED off
+    #[default(50)]
+    led_brightness: u8,
+    // Idle LED shows signal strength from red (weak) through yellow to green (strong)
+    #[default(false)]
+    rssi_led: bool,
+    // LED blinks: slow while scanning, fast while a gate command is in flight; false - always solid
+    #[default(true)]
+    led_blink: bool,
+    // LED stays red this long after a failed button command
+    #[default(2000)]
+    ack_error_ms: u32,
+    // Each flash of the green double blink after a successful button command, 0 - no blink
+    #[default(150)]
+    ack_success_ms: u32,
+}
+
+/// Control role, run by the GateControl binary and by Gate with role = "control"
+pub fn run() -> anyhow::Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    // Config problems are logged before anything uses the config
+    config();
+    let led = match init_peripherals() {
+        Ok(led) => led,
+        Err(e) => {
+            // Delay keeps a wiring or pin conflict problem from flooding the log with restarts
+            error!(
+                "Peripheral initialization failed, restarting in 10 seconds: {:?}",
+                e
+            );
+            FreeRtos::delay_ms(10_000);
+            reset::restart();
+        }
+    };
+    if let Some(led) = led {
+        if let Err(e) = led::start(led) {
+            warn!(
+                "Can not start LED task, continuing without LED feedback: {}",
+                e
+            );
+        }
+    }
+    button_at_power_on();
+    let app_config = config();
+    let mut settings = settings::load();
+    threshold_server::init(&settings);
+    // Report malformed gate URLs at startup rather than on the first command
+    lazy_static::initialize(&GATE_URLS);
+    let gate_cert_trusted = trust_gate_cert();
+
+    // Auto-open is armed until the gate is opened on approach,
+    // then RSSI has to rise above min_rssi to arm it again
+    let mut armed = true;
+    // In low power mode provisioning is possible only on the first connect after power on,
+    // later a missing access point means sleep
+    let mut first_connect = true;
+    let mut sleep_requested = false;
+    // Button pressed to wake up, SBS is sent after reconnect
+    let mut sbs_pending = false;
+    loop {
+        // Reconnect loop, then WiFi connection lost
+        'reconnect_loop: {
+            // Yellow, slow blink while scanning
+            led::show(RGB8::new(50, 50, 0), Blink::Slow);
+            let networks = networks(&settings.wifi_ssid, &settings.wifi_psk);
+            let provisioning_allowed = app_config.sleep_secs == 0 || first_connect;
+            first_connect = false;
+            let max_missed_scans = if provisioning_allowed {
+                app_config.provision_after_scans
+            } else {
+                SLEEP_MISSED_SCANS
+            };
+            let Some(mut wifi) = connect_wifi(&networks, max_missed_scans).unwrap() else {
+                if !provisioning_allowed {
+                    sleep_requested = true;
+                    break 'reconnect_loop;
+                }
+                // Cyan, slow blink
+                led::show(RGB8::new(0, 50, 50), Blink::Slow);
+                match provisioning::run_portal(&networks) {
+                    Ok(true) => {
+                        settings = settings::load();
+                        threshold_server::init(&settings);
+                    }
+                    Ok(false) => {}
+                    Err(e) => error!("Provisioning access point failed: {}", e),
+                }
+                break 'reconnect_loop;
+            };
+            // Scan RSSI is measured before association and may differ from the AP info read
+            // in the poll loop, so the approach decision takes a live reading at once,
+            // the same metric as the loop
+            let connect_rssi = match wifi.0.driver_mut().get_ap_info() {
+                Ok(ap_info) => ap_info.signal_strength,
+                Err(e) => {
+                    warn!("Can not get AP info, using scan RSSI {}: {}", wifi.1, e);
+                    wifi.1
+                }
+            };
+            info!(
+                "WiFi connected with rssi {} (scan {}), distance ~{:.1} m",
+                connect_rssi,
+                wifi.1,
+                path_loss().distance(connect_rssi)
+            );
+            // mDNS is needed to resolve .local host names in gate URLs
+            let _mdns = EspMdns::take()?;
+            let mut client = Client::wrap(EspHttpConnection::new(&HttpConfiguration {
+                timeout: Some(Duration::from_millis(app_config.http_timeout_ms as u64)),
+                use_global_ca_store: gate_cert_trusted,
+                ..Default::default()
+            })?);
+            // The approach decision on connection averages as many readings as the poll loop,
+            // taken at the poll interval
+            let mut rssi_window = RssiWindow::new(app_config.rssi_window, connect_rssi);
+            for _ in 1..app_config.rssi_window {
+                FreeRtos::delay_ms(100);
+                match wifi.0.driver_mut().get_ap_info() {
+                    Ok(ap_info) => {
+                        rssi_window.push(ap_info.signal_strength);
+                    }
+                    Err(e) => warn!("Can not get AP info: {}", e),
+                }
+            }
+            let connect_average = rssi_window.average();
+            if app_config.rssi_window > 1 {
+                info!(
+                    "Average rssi {} of {} readings",
+                    connect_average, app_config.rssi_window
+                );
+            }
+            threshold_server::set_rssi(connect_rssi);
+            let _threshold_server = if app_config.threshold_server {
+                Some(threshold_server::start()?)
+            } else {
+                None
+            };
+            armed = approach::rearm(armed, connect_rssi, threshold_server::current().min_rssi);
+            let threshold = approach_threshold(threshold_server::current().max_rssi);
+            let mut dwell = Dwell::default();
+            if approach::should_open(armed, connect_average, threshold) {
+                if app_config.approach_dwell_ms > 0 {
+                    info!(
+                        "Rssi is low. Opening gate if it stays low for {} ms",
+                        app_config.approach_dwell_ms
+                    );
+                }
+                dwell.start();
+            }
+            if dwell.sample(connect_average, threshold, app_config.approach_dwell_ms) {
+                armed = false;
+                approach_open(&mut client)?;
+            }
+
+            if sbs_pending {
+                sbs_pending = false;
+                // Blue, fast blink while the command is in flight
+                led::show(RGB8::new(0, 0, 50), Blink::Fast);
+                let url = button_url(&mut client);
+                let outcome = command_request_with_retries(url, &mut client);
+                button_feedback(&outcome);
+            }
+
+            // Green, solid while idle
+            led::show(RGB8::new(0, 50, 0), Blink::Solid);
+            let gate_sbs = hardware().gate_sbs.clone();
+            let gate_sbs = gate_sbs.lock();
+
+            let mut departure = Departure::default();
+            let mut last_rssi = connect_rssi;
+            let mut last_ping: Option<Instant> = None;
+            // Poll SBS pin loop
+            loop {
+                // AP info may be briefly unavailable while roaming between mesh nodes,
+                // the last good RSSI is used then and the LED is left as is
+                let (rssi, fresh) = match wifi.0.driver_mut().get_ap_info() {
+                    Ok(ap_info) => (ap_info.signal_strength, true),
+                    Err(e) => {
+                        warn!("Can not get AP info, using last RSSI {}: {}", last_rssi, e);
+                        (last_rssi, false)
+                    }
+                };
+                last_rssi = rssi;
+                threshold_server::set_rssi(rssi);
+                // A repeated last RSSI is not a new sample
+                let average = if fresh {
+                    rssi_window.push(rssi)
+                } else {
+                    rssi_window.average()
+                };
+                // Thresholds may be changed by POST /threshold meanwhile
+                let thresholds = threshold_server::current();
+                let threshold = approach_threshold(thresholds.max_rssi);
+                if app_config.rssi_window > 1 {
+                    info!(
+                        "RSSI: {}, average {}, distance ~{:.1} m",
+                        rssi,
+                        average,
+                        path_loss().distance(average)
+                    );
+                } else {
+                    info!(
+                        "RSSI: {}, distance ~{:.1} m",
+                        rssi,
+                        path_loss().distance(rssi)
+                    );
+                }
+                if app_config.rssi_led && fresh {
+                    led::show(rssi_color(rssi), Blink::Solid);
+                }
+                let rearmed = approach::rearm(armed, rssi, thresholds.min_rssi);
+                if rearmed && !armed {
+                    info!("Rssi is above {}. Auto-open armed", thresholds.min_rssi);
+                }
+                armed = rearmed;
+                if dwell.pending() {
+                    if dwell.sample(average, threshold, app_config.approach_dwell_ms) {
+                        armed = false;
+                        approach_open(&mut client)?;
+                        // Green
+                        led::show(RGB8::new(0, 50, 0), Blink::Solid);
+                    } else if !dwell.pending() {
+                        info!("Closer than {}, passing by. Auto-open cancelled", threshold);
+                    }
+                }
+                if app_config.departure_rssi != 0
+                    && fresh
+                    && departure.sample(rssi, app_config.departure_rssi)
+                {
+                    departure_close(&mut client)?;
+                    // Green
+                    led::show(RGB8::new(0, 50, 0), Blink::Solid);
+                }
+                if gate_sbs.is_low() {
+                    let url = if long_press(&gate_sbs) {
+                        info!("Button long press. Opening gate");
+                        GATE_URLS.open.as_str()
+                    } else {
+                        button_url(&mut client)
+                    };
+                    // Blue, fast blink while the command is in flight
+                    led::show(RGB8::new(0, 0, 50), Blink::Fast);
+                    let outcome = command_request_with_retries(url, &mut client);
+                    button_feedback(&outcome);
+                    // Avoid contact bounce and duplicate sensing
+                    FreeRtos::delay_ms(100);
+                    while gate_sbs.is_low() {
+                        FreeRtos::delay_ms(100);
+                    }
+                    // Green
+                    led::show(RGB8::new(0, 50, 0), Blink::Solid);
+                } else {
+                    FreeRtos::delay_ms(100);
+                }
+
+                let ping_interval = Duration::from_secs(app_config.ping_secs as u64);
+                if app_config.ping_secs > 0
+                    && last_ping.map_or(true, |last_ping| last_ping.elapsed() >= ping_interval)
+                {
+                    last_ping = Some(Instant::now());
+                    if let Err(e) = ping(rssi, &mut client) {
+                        warn!("Gate ping failed: {}", e);
+                    }
+                }
+
+                // Nothing to do: no approach and button released
+                if app_config.sleep_secs > 0 && !dwell.pending() {
+                    sleep_requested = true;
+                    break 'reconnect_loop;
+                }
+
+                if !wifi.0.driver_mut().is_connected().unwrap() {
+                    info!("WiFi connection lost. Pause to avoid wrong reconnection");
+                    // Violet, slow blink
+                    led::show(RGB8::new(50, 0, 50), Blink::Slow);
+                    FreeRtos::delay_ms(60000);
+                    info!("Reconnecting WiFi");
+                    break 'reconnect_loop;
+                }
+            }
+        }
+        // WiFi is dropped when leaving the reconnect block
+        if sleep_requested {
+            sleep_requested = false;
+            info!("Sleeping {} seconds", app_config.sleep_secs);
+            led::off();
+            sbs_pending = power::light_sleep(app_config.sleep_secs);
+        }
+    }
+}
+/// SBS button held at power on: erase settings stored in NVS and reboot if it is held for
+/// `factory_reset_secs`, LED blinks violet fast meanwhile. Released earlier, the LED shows
+/// the identify rainbow, which runs on while the unit connects
+fn button_at_power_on() {
+    let gate_sbs = hardware().gate_sbs.clone();
+    let gate_sbs = gate_sbs.lock();
+    if gate_sbs.is_high() {
+        return;
+    }
+    let factory_reset = config().factory_reset_secs > 0;
+    if factory_reset {
+        info!(
+            "Button held at power on. Hold it {} seconds to erase settings",
+            config().factory_reset_secs
+        );
+        // Violet, fast blink
+        led::show(RGB8::new(50, 0, 50), Blink::Fast);
+    }
+    let hold = Duration::from_secs(config().factory_reset_secs as u64);
+    let pressed = Instant::now();
+    while gate_sbs.is_low() {
+        if factory_reset && pressed.elapsed() >= hold {
+            match settings::erase() {
+                Ok(erased) if erased.is_empty() => {
+                    info!("Factory reset: no settings stored in NVS. Rebooting")
+                }
+                Ok(erased) => info!(
+                    "Factory reset: {} erased from NVS. Rebooting",
+                    erased.join(", ")
+                ),
+                Err(e) => error!("Factory reset failed, rebooting: {}", e),
+            }
+            // Violet, solid
+            led::show(RGB8::new(50, 0, 50), Blink::Solid);
+            FreeRtos::delay_ms(1000);
+            reset::restart();
+        }
+        FreeRtos::delay_ms(100);
+    }
+    if factory_reset {
+        info!("Button released. Factory reset cancelled");
+    }
+    led::off();
+    info!("Identifying");
+    led::identify();
+}
+/// Add `gate_cert` to the global CA store, so the self-signed GateServer certificate is accepted.
+fn trust_gate_cert() -> bool {
+    if config().gate_cert.is_empty() {
+        return false;
+    }
+    // PEM length passed to ESP-IDF includes the terminating NUL
+    let pem = match CString::new(config().gate_cert) {
+        Ok(pem) => pem,
+        Err(e) => {
+            error!("gate_cert is not valid: {}", e);
+            return false;
+        }
+    };
+    let pem = pem.as_bytes_with_nul();
+    match esp!(unsafe { esp_tls_set_global_ca_store(pem.as_ptr(), pem.len() as u32) }) {
+        Ok(()) => {
+            info!("GateServer certificate added to CA store");
+            true
+        }
+        Err(e) => {
+            error!("Can not add GateServer certificate to CA store: {}", e);
+            false
+        }
+    }
+}
+/// Auto-open on approach: open command, then wait for GateServer to report the gate opened.
+/// LED blinks red fast meanwhile. The command is sent only if GateServer reports the gate closed,
+/// so a gate left open gets no redundant relay pulse. It is not tried in locked mode either,
+/// where GateServer refuses open commands, nor within auto_open_cooldown_secs after the last one
+fn approach_open(client: &mut Client<EspHttpConnection>) -> anyhow::Result<()> {
+    let cooldown = Duration::from_secs(config().auto_open_cooldown_secs as u64);
+    let last_auto_open = *LAST_AUTO_OPEN.clone().lock();
+    if let Some(elapsed) = last_auto_open.map(|last| last.elapsed()) {
+        if elapsed < cooldown {
+            info!(
+                "Rssi is low, but gate was auto-opened {} s ago. Auto-open skipped",
+                elapsed.as_secs()
+            );
+            return Ok(());
+        }
+    }
+    let body = match gate_request_body(Method::Get, &GATE_URLS.status, client) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Gate status request failed, auto-open skipped: {}", e);
+            return Ok(());
+        }
+    };
+    if body.contains("\"mode\":\"locked\"") {
+        info!("Rssi is low, but gate is locked. Auto-open skipped");
+        return Ok(());
+    }
+    match parse_gate_status(&body) {
+        Some(GateState::Closed) => {}
+        Some(status) => {
+            info!("Rssi is low, but gate is {}. Auto-open skipped", status);
+            return Ok(());
+        }
+        None => {
+            error!("No gate status in response body, auto-open skipped");
+            return Ok(());
+        }
+    }
+    info!("Rssi is low. Opening gate");
+    // Red, fast blink until the gate reports opened
+    led::show(RGB8::new(50, 0, 0), Blink::Fast);
+    match command_request_with_retries(&GATE_URLS.open, client) {
+        Outcome::Success(_) => {
+            *LAST_AUTO_OPEN.clone().lock() = Some(Instant::now());
+            if wait_gate_status(GateState::Open, config().open_confirm_secs, client) {
+                info!("Gate opening confirmed");
+            } else {
+                error!("Gate did not report opened in time");
+                FreeRtos::delay_ms(2000);
+            }
+        }
+        outcome => {
+            error!("Gate open request failed: {}", outcome);
+            // Red
+            led::show(RGB8::new(50, 0, 0), Blink::Solid);
+            FreeRtos::delay_ms(1000);
+        }
+    }
+    Ok(())
+}
+/// Close on departure: close command if the gate is open. LED blinks blue fast meanwhile.
+/// Skipped in hold_open mode, where the gate is held open on purpose
+fn departure_close(client: &mut Client<EspHttpConnection>) -> anyhow::Result<()> {
+    let body = match gate_request_body(Method::Get, &GATE_URLS.status, client) {
+        Ok(body) => body,
+        Err(e) => {
+            error!(
+                "Gate status request failed, close on departure skipped: {}",
+                e
+            );
+            return Ok(());
+        }
+    };
+    if body.contains("\"mode\":\"hold_open\"") {
+        info!("Rssi is below departure_rssi, but gate is held open. Close skipped");
+        return Ok(());
+    }
+    if parse_gate_status(&body) != Some(GateState::Open) {
+        info!("Rssi is below departure_rssi, gate is not open. Close skipped");
+        return Ok(());
+    }
+    info!("Rssi is below departure_rssi. Closing gate");
+    // Blue, fast blink while the command is in flight
+    led::show(RGB8::new(0, 0, 50), Blink::Fast);
+    let outcome = command_request_with_retries(&GATE_URLS.close, client);
+    if !outcome.is_success() {
+        error!("Gate close request failed: {}", outcome);
+    }
+    Ok(())
+}
+/// Path loss model by `rssi_at_1m` and `path_loss_exponent`, for the distance estimation
+fn path_loss() -> PathLoss {
+    PathLoss {
+        rssi_at_1m: config().rssi_at_1m,
+        exponent: config().path_loss_exponent,
+    }
+}
+/// Approach threshold: `open_distance_m` if configured, `max_rssi` otherwise
+fn approach_threshold(max_rssi: i8) -> Threshold {
+    Threshold::new(max_rssi, config().open_distance_m, path_loss())
+}
+/// Idle LED color for `rssi`: red up to RSSI_WEAK, yellow in the middle, green from RSSI_STRONG.
+fn rssi_color(rssi: i8) -> RGB8 {
+    let range = (RSSI_STRONG - RSSI_WEAK) as i32;
+    let level = (rssi.clamp(RSSI_WEAK, RSSI_STRONG) - RSSI_WEAK) as i32;
+    // Both components reach full brightness 50 at the middle of the range
+    let red = (100 * (range - level) / range).min(50) as u8;
+    let green = (100 * level / range).min(50) as u8;
+    RGB8::new(red, green, 0)
+}
+/// LED acknowledgement of a button command, so the user at the gate knows whether it worked:
+/// green double blink of `ack_success_ms` flashes on success, red for `ack_error_ms` on failure.
+/// Blinks regardless of `led_blink`: the pattern is driven here, not by the animator
+fn button_feedback(outcome: &Outcome) {
+    if !outcome.is_success() {
+        error!("Gate button request failed: {}", outcome);
+        // Red
+        led::show(RGB8::new(50, 0, 0), Blink::Solid);
+        FreeRtos::delay_ms(config().ack_error_ms);
+        return;
+    }
+    if config().ack_success_ms == 0 {
+        return;
+    }
+    for _ in 0..2 {
+        led::off();
+        FreeRtos::delay_ms(config().ack_success_ms);
+        // Green
+        led::show(RGB8::new(0, 50, 0), Blink::Solid);
+        FreeRtos::delay_ms(config().ack_success_ms);
+    }
+}
+/// Whether the pressed button is held for `long_press_ms`. Waits until that time or the release,
+/// whichever comes first. Always false with `long_press_ms` 0, then the press is handled at once
+fn long_press(gate_sbs: &PinDriver<'static, board::SbsButtonPin, Input>) -> bool {
+    if config().long_press_ms == 0 {
+        return false;
+    }
+    let long_press = Duration::from_millis(config().long_press_ms as u64);
+    let pressed = Instant::now();
+    while gate_sbs.is_low() {
+        if pressed.elapsed() >= long_press {
+            return true;
+        }
+        FreeRtos::delay_ms(20);
+    }
+    false
+}
+/// Gate command URL for a button press. With `smart_button` the current gate status decides:
+/// closed - open, opened - close, moving or unknown - SBS, which stops a moving gate.
+fn button_url(client: &mut Client<EspHttpConnection>) -> &'static str {
+    if !config().smart_button {
+        return &GATE_URLS.sbs;
+    }
+    match gate_request(Method::Get, &GATE_URLS.status, client) {
+        Ok(GateState::Closed) => &GATE_URLS.open,
+        Ok(GateState::Open) => &GATE_URLS.close,
+        // GateServer refuses commands on a sensor fault and replies so
+        Ok(GateState::Moving | GateState::Fault) => &GATE_URLS.sbs,
+        Err(e) => {
+            error!("Gate status request failed, falling back to SBS: {}", e);
+            &GATE_URLS.sbs
+        }
+    }
+}
+/// Poll gate status once a second until it equals `expected` or `timeout_secs` elapse.
+fn wait_gate_status(
+    expected: GateState,
+    timeout_secs: u32,
+    client: &mut Client<EspHttpConnection>,
+) -> bool {
+    for _ in 0..timeout_secs {
+        match gate_request(Method::Get, &GATE_URLS.status, client) {
+            Ok(status) if status == expected => return true,
+            Ok(status) => info!("Gate status {}, waiting for {}", status, expected),
+            Err(e) => error!("Gate status request failed: {}", e),
+        }
+        FreeRtos::delay_ms(1000);
+    }
+    false
+}
+/// Heartbeat GET `/ping?rssi=N`, so GateServer knows the unit is in range. Logged only on failure
+fn ping(rssi: i8, client: &mut Client<EspHttpConnection>) -> anyhow::Result<()> {
+    let url = format!("{}?rssi={}", GATE_URLS.ping, rssi);
+    let headers = [("X-Gate-Token", config().gate_token)];
+    let mut response = client.request(Method::Get, &url, &headers)?.submit()?;
+    // Short body is read out, so the connection is ready for the next request
+    let mut buf = [0u8; 64];
+    io::try_read_full(&mut response, &mut buf).map_err(|e| e.0)?;
+    match response.status() {
+        200 => Ok(()),
+        status => Err(anyhow::anyhow!("{} replied {}", GATE_URLS.ping, status)),
+    }
+}
+/// Send a gate command as HTTP POST, retrying up to `http_retries` times while the outcome
+/// allows it: a refused (4xx) command is not repeated.
+fn command_request_with_retries(url: &str, client: &mut Client<EspHttpConnection>) -> Outcome {
+    let attempts = config().http_retries.max(1);
+    let mut attempt = 1;
+    loop {
+        let outcome = match gate_response(Method::Post, url, client) {
+            Ok((status, body)) => {
+                if let Some(rssi) = parse_server_rssi(&body) {
+                    info!("GateServer RSSI {}", rssi);
+                }
+                Outcome::from_response(status, &body)
+            }
+            Err(e) => Outcome::Failed(e),
+        };
+        if !outcome.retry() || attempt >= attempts {
+            return outcome;
+        }
+        error!("Attempt {} of {} failed: {}", attempt, attempts, outcome);
+        attempt += 1;
+        FreeRtos::delay_ms(500);
+    }
+}
+/// Send an HTTP request without body and return the gate status from the response.
+fn gate_request(
+    method: Method,
+    url: &str,
+    client: &mut Client<EspHttpConnection>,
+) -> anyhow::Result<GateState> {
+    let body = gate_request_body(method, url, client)?;
+    parse_gate_status(&body).ok_or_else(|| anyhow::anyhow!("No gate status in response body"))
+}
+/// Send an HTTP request without body and return the response body, an error unless 2xx.
+fn gate_request_body(
+    method: Method,
+    url: &str,
+    client: &mut Client<EspHttpConnection>,
+) -> anyhow::Result<String> {
+    let (status, body) = gate_response(method, url, client)?;
+    if !(200..300).contains(&status) {
+        anyhow::bail!("Unexpected HTTP status {}", status);
+    }
+    Ok(body)
+}
+/// Send an HTTP request without body and return the response status and body.
+/// With http_keep_alive the client reuses the connection of the previous request to the same
+/// host, which saves the TCP and TLS handshakes of every status poll and command
+fn gate_response(
+    method: Method,
+    url: &str,
+    client: &mut Client<EspHttpConnection>,
+) -> anyhow::Result<(u16, String)> {
+    let keep_alive = config().http_keep_alive;
+    // Explicit empty body, otherwise POST is sent chunked
+    let headers = [
+        ("accept", "application/json"),
+        ("X-Gate-Token", config().gate_token),
+        ("Content-Length", "0"),
+        (
+            "Connection",
+            if keep_alive { "keep-alive" } else { "close" },
+        ),
+    ];
+
+    // Send request
+    let request = client.request(method, url, &headers)?;
+    info!("-> {:?} {}", method, url);
+    let mut response = request.submit()?;
+
+    // Process response
+    let status = response.status();
+    info!("<- {}", status);
+    // Gate status with schedule, mode and IPv6 addresses fits, command replies are much shorter
+    let mut buf = [0u8; 1024];
+    let bytes_read = io::try_read_full(&mut response, &mut buf).map_err(|e| e.0)?;
+    info!("Read {} bytes", bytes_read);
+    let body = match std::str::from_utf8(&buf[0..bytes_read]) {
+        Ok(body_string) => {
+            info!(
+                "Response body (truncated to {} bytes): {:?}",
+                buf.len(),
+                body_string
+            );
+            body_string
+        }
+        Err(e) => {
+            error!("Error decoding response body: {}", e);
+            ""
+        }
+    };
+    let body = body.to_string();
+    if !keep_alive {
+        // Body is read to the end, so the next request does not read a closed connection
+        let mut rest = [0u8; 256];
+        while response.read(&mut rest)? > 0 {}
+        drop(response);
+        if let Err(e) = esp!(unsafe { esp_http_client_close(client.connection().handle()) }) {
+            warn!("Can not close connection to GateServer: {}", e);
+        }
+    }
+    Ok((status, body))
+}
@@ -0,0 +1,53 @@
+// Result of a gate command as GateServer replied it. Any 2xx reply is a success, with or without
+// a gate status in the body (response_format text), and is never retried: the relay has already
+// been pulsed, a repeated SBS would stop or reverse the gate. A 4xx reply
+// means the command was refused (token, locked mode, rate limit) and is not retried, a 5xx one
+// means GateServer failed to execute it (e.g. a relay fault) and is retried like a lost request.
+use core::fmt;
+use gate_logic::reply::parse_gate_status;
+
+use crate::gate_state::GateState;
+
+pub enum Outcome {
+    // 2xx with the gate status of the reply, None - the reply has none
+    Success(Option<GateState>),
+    // 4xx
+    ClientError(u16),
+    // 5xx
+    ServerError(u16),
+    // No reply or an unexpected status
+    Failed(anyhow::Error),
+}
+
+impl Outcome {
+    // Outcome of a reply with HTTP status and body
+    pub fn from_response(status: u16, body: &str) -> Self {
+        match status {
+            200..=299 => Outcome::Success(parse_gate_status(body)),
+            400..=499 => Outcome::ClientError(status),
+            500..=599 => Outcome::ServerError(status),
+            _ => Outcome::Failed(anyhow::anyhow!("Unexpected HTTP status {}", status)),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        matches!(self, Outcome::Success(_))
+    }
+
+    // Another attempt may succeed
+    pub fn retry(&self) -> bool {
+        matches!(self, Outcome::ServerError(_) | Outcome::Failed(_))
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Success(Some(gate_status)) => write!(f, "done, gate {}", gate_status),
+            Outcome::Success(None) => write!(f, "done"),
+            Outcome::ClientError(status) => write!(f, "refused by GateServer with {}", status),
+            Outcome::ServerError(status) => write!(f, "GateServer failed with {}", status),
+            Outcome::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
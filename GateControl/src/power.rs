@@ -0,0 +1,26 @@
+use esp_idf_svc::sys::{
+    esp_light_sleep_start, esp_sleep_enable_gpio_wakeup, esp_sleep_enable_timer_wakeup,
+    esp_sleep_get_wakeup_cause, esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO,
+    gpio_int_type_t_GPIO_INTR_LOW_LEVEL, gpio_wakeup_disable, gpio_wakeup_enable,
+};
+use log::info;
+
+use crate::board::SBS_BUTTON_GPIO_NUM;
+
+// Light sleep for `secs` or until the SBS button (active low) is pressed.
+// ESP32-C3 can not wake from deep sleep on GPIO9, and light sleep keeps RAM, so
+// auto-open state survives. WiFi has to be dropped before.
+// Returns true if woken by the button.
+pub fn light_sleep(secs: u32) -> bool {
+    unsafe {
+        esp_sleep_enable_timer_wakeup(secs as u64 * 1_000_000);
+        gpio_wakeup_enable(SBS_BUTTON_GPIO_NUM, gpio_int_type_t_GPIO_INTR_LOW_LEVEL);
+        esp_sleep_enable_gpio_wakeup();
+        esp_light_sleep_start();
+        gpio_wakeup_disable(SBS_BUTTON_GPIO_NUM);
+    }
+    let by_button =
+        unsafe { esp_sleep_get_wakeup_cause() } == esp_sleep_source_t_ESP_SLEEP_WAKEUP_GPIO;
+    info!("Woken up by {}", if by_button { "button" } else { "timer" });
+    by_button
+}
@@ -0,0 +1,208 @@
+use embedded_svc::{http::Method, io::Write};
+use esp_idf_hal::{delay::FreeRtos, peripheral::Peripheral};
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    hal::io::EspIOError,
+    http::server::{Configuration as HttpServerConfiguration, EspHttpServer},
+    wifi::{
+        AccessPointConfiguration, AuthMethod, BlockingWifi, ClientConfiguration, Configuration,
+        EspWifi,
+    },
+};
+use gate_logic::http::form_field;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::web::read_body;
+use crate::{config, hardware, settings};
+
+// How often the configured access point is looked for while the portal is running
+const SCAN_INTERVAL_SECS: u64 = 15;
+
+// Run provisioning access point with WiFi settings form.
+// Returns true if new credentials were saved, false if one of the configured access
+// points was found again or provision_timeout_secs elapsed.
+pub fn run_portal(networks: &[(String, String)]) -> anyhow::Result<bool> {
+    info!(
+        "Starting provisioning access point {}",
+        config().provision_ap_ssid
+    );
+    let _nvs_default_partition = hardware().nvs_partition.clone();
+    let peripherals = hardware().peripherals.clone();
+    let mut peripherals = peripherals.lock();
+    let modem = unsafe { peripherals.modem.clone_unchecked() };
+    drop(peripherals);
+    let sysloop = EspSystemEventLoop::take()?;
+    let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), None)?;
+    let mut wifi = BlockingWifi::wrap(&mut esp_wifi, sysloop)?;
+    // Station stays enabled to look for the configured access point
+    wifi.set_configuration(&Configuration::Mixed(
+        ClientConfiguration::default(),
+        AccessPointConfiguration {
+            ssid: config()
+                .provision_ap_ssid
+                .try_into()
+                .expect("Could not parse the given SSID into AP config"),
+            password: config()
+                .provision_ap_psk
+                .try_into()
+                .expect("Could not parse the given password into AP config"),
+            auth_method: if config().provision_ap_psk.is_empty() {
+                AuthMethod::None
+            } else {
+                AuthMethod::WPA2Personal
+            },
+            ..Default::default()
+        },
+    ))?;
+    wifi.start()?;
+    let portal_ip = wifi.wifi().ap_netif().get_ip_info()?.ip;
+    info!("Provisioning form is available at http://{}/", portal_ip);
+
+    let saved = Arc::new(Mutex::new(false));
+    let stop_dns = Arc::new(Mutex::new(false));
+    spawn_dns(portal_ip, stop_dns.clone())?;
+    let mut server = EspHttpServer::new(&HttpServerConfiguration {
+        uri_match_wildcard: true,
+        ..Default::default()
+    })?;
+    let saved_flag = saved.clone();
+    server.fn_handler(
+        "/save",
+        Method::Post,
+        move |mut request| -> core::result::Result<(), EspIOError> {
+            let body = read_body(&mut request, 256)?;
+            let wifi_ssid = form_field(&body, "wifi_ssid").unwrap_or_default();
+            let wifi_psk = form_field(&body, "wifi_psk").unwrap_or_default();
+            if wifi_ssid.is_empty()
+                || wifi_ssid.len() > 32
+                || (!wifi_psk.is_empty() && !(8..=64).contains(&wifi_psk.len()))
+            {
+                warn!("Provisioning rejected: invalid SSID or password length");
+                let mut response = request.into_response(
+                    400,
+                    Some("Bad Request"),
+                    &[("Content-Type", "text/plain; charset=utf-8")],
+                )?;
+                response.write_all("SSID 1..32 байт, пароль пустой или 8..64 байт".as_bytes())?;
+                return Ok(());
+            }
+            match settings::save_wifi(&wifi_ssid, &wifi_psk) {
+                Ok(()) => {
+                    *saved_flag.lock() = true;
+                    let mut response = request.into_response(
+                        200,
+                        Some("OK"),
+                        &[("Content-Type", "text/plain; charset=utf-8")],
+                    )?;
+                    response
+                        .write_all(format!("Сохранено, подключение к {}", wifi_ssid).as_bytes())?;
+                }
+                Err(e) => {
+                    error!("Can not save WiFi credentials to NVS: {}", e);
+                    let mut response = request.into_response(500, Some("NVS Error"), &[])?;
+                    response.write_all(b"Can not save settings")?;
+                }
+            }
+            Ok(())
+        },
+    )?;
+    // Any other page opens the form, so captive portal detection of phones shows it
+    server.fn_handler(
+        "/*",
+        Method::Get,
+        |request| -> core::result::Result<(), EspIOError> {
+            let mut response = request.into_ok_response()?;
+            response.write_all(include_str!("provision.html").as_bytes())?;
+            Ok(())
+        },
+    )?;
+
+    let deadline = Instant::now() + Duration::from_secs(config().provision_timeout_secs as u64);
+    let mut next_scan = Instant::now() + Duration::from_secs(SCAN_INTERVAL_SECS);
+    let result = loop {
+        FreeRtos::delay_ms(1000);
+        if *saved.lock() {
+            // Let the response reach the browser before the access point is stopped
+            FreeRtos::delay_ms(1000);
+            info!("WiFi credentials provisioned");
+            break true;
+        }
+        if Instant::now() >= deadline {
+            info!("Provisioning timeout, retrying configured access point");
+            break false;
+        }
+        if Instant::now() >= next_scan {
+            next_scan = Instant::now() + Duration::from_secs(SCAN_INTERVAL_SECS);
+            match wifi.scan() {
+                Ok(ap_infos) => {
+                    if let Some(ours) = ap_infos
+                        .iter()
+                        .find(|a| networks.iter().any(|(ssid, _)| a.ssid == ssid.as_str()))
+                    {
+                        info!("Configured access point {} is back", ours.ssid);
+                        break false;
+                    }
+                }
+                Err(e) => warn!("Scan during provisioning failed: {}", e),
+            }
+        }
+    };
+    *stop_dns.lock() = true;
+    drop(server);
+    wifi.stop()?;
+    Ok(result)
+}
+
+// Answer every DNS query with the portal address until stopped
+fn spawn_dns(portal_ip: Ipv4Addr, stop: Arc<Mutex<bool>>) -> anyhow::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:53")?;
+    socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+    std::thread::Builder::new()
+        .stack_size(4096)
+        .spawn(move || {
+            let mut buf = [0u8; 512];
+            while !*stop.lock() {
+                let Ok((len, peer)) = socket.recv_from(&mut buf) else {
+                    continue;
+                };
+                if let Some(reply) = dns_reply(&buf[..len], portal_ip) {
+                    if let Err(e) = socket.send_to(&reply, peer) {
+                        warn!("DNS reply to {} failed: {}", peer, e);
+                    }
+                }
+            }
+        })?;
+    Ok(())
+}
+
+// A record reply for a single question query, None - not a query
+fn dns_reply(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    // Header is 12 bytes, QR bit clear and exactly one question
+    if query.len() < 12 || query[2] & 0x80 != 0 || query[4..6] != [0, 1] {
+        return None;
+    }
+    // Question name labels end with zero byte, then type and class
+    let mut end = 12;
+    while *query.get(end)? != 0 {
+        end += 1 + query[end] as usize;
+    }
+    end += 5;
+    if end > query.len() {
+        return None;
+    }
+    let mut reply = Vec::with_capacity(end + 16);
+    reply.extend_from_slice(&query[..2]);
+    // Response, recursion desired and available
+    reply.extend_from_slice(&[0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0]);
+    reply.extend_from_slice(&query[12..end]);
+    // Name pointer to the question, type A, class IN, TTL 60, 4 bytes address
+    reply.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+    reply.extend_from_slice(&ip.octets());
+    Some(reply)
+}
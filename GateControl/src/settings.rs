@@ -0,0 +1,120 @@
+use esp_idf_svc::nvs::{EspDefaultNvs, EspNvs};
+use log::{error, info, warn};
+
+use crate::{config, hardware};
+
+const NVS_NAMESPACE: &str = "gate_cfg";
+const NVS_KEYS: &[&str] = &["wifi_ssid", "wifi_psk", "max_rssi", "min_rssi"];
+
+// Settings changeable without reflashing: validated config() values overridden from NVS
+pub struct Settings {
+    pub wifi_ssid: String,
+    pub wifi_psk: String,
+    pub max_rssi: i8,
+    pub min_rssi: i8,
+}
+
+fn open_nvs() -> anyhow::Result<EspDefaultNvs> {
+    Ok(EspNvs::new(
+        hardware().nvs_partition.clone(),
+        NVS_NAMESPACE,
+        true,
+    )?)
+}
+
+pub fn load() -> Settings {
+    let mut settings = Settings {
+        wifi_ssid: config().wifi_ssid.to_string(),
+        wifi_psk: config().wifi_psk.to_string(),
+        max_rssi: config().max_rssi,
+        min_rssi: config().min_rssi,
+    };
+    let nvs = match open_nvs() {
+        Ok(nvs) => nvs,
+        Err(e) => {
+            warn!("Can not open settings in NVS, using compiled values: {}", e);
+            return settings;
+        }
+    };
+    if let Some(wifi_ssid) = read_str(&nvs, "wifi_ssid") {
+        info!("wifi_ssid loaded from NVS");
+        settings.wifi_ssid = wifi_ssid;
+    }
+    if let Some(wifi_psk) = read_str(&nvs, "wifi_psk") {
+        info!("wifi_psk loaded from NVS");
+        settings.wifi_psk = wifi_psk;
+    }
+    match nvs.get_i8("max_rssi") {
+        Ok(Some(max_rssi)) if max_rssi >= 0 => {
+            error!(
+                "max_rssi {} from NVS would open the gate on every connection, ignored",
+                max_rssi
+            );
+        }
+        Ok(Some(max_rssi)) => {
+            info!("max_rssi {} loaded from NVS", max_rssi);
+            settings.max_rssi = max_rssi;
+        }
+        Ok(None) => {}
+        Err(e) => error!("Can not read max_rssi from NVS: {}", e),
+    }
+    match nvs.get_i8("min_rssi") {
+        Ok(Some(min_rssi)) if min_rssi >= 0 => {
+            error!(
+                "min_rssi {} from NVS is not a signal level, ignored",
+                min_rssi
+            );
+        }
+        Ok(Some(min_rssi)) => {
+            info!("min_rssi {} loaded from NVS", min_rssi);
+            settings.min_rssi = min_rssi;
+        }
+        Ok(None) => {}
+        Err(e) => error!("Can not read min_rssi from NVS: {}", e),
+    }
+    settings
+}
+
+// String value from NVS, None - not stored (e.g. first boot) or unreadable
+fn read_str(nvs: &EspDefaultNvs, key: &str) -> Option<String> {
+    let mut buf = [0u8; 128];
+    match nvs.get_str(key, &mut buf) {
+        Ok(value) => value.map(str::to_string),
+        Err(e) => {
+            error!("Can not read {} from NVS: {}", key, e);
+            None
+        }
+    }
+}
+
+// Store WiFi credentials entered in the provisioning portal
+pub fn save_wifi(wifi_ssid: &str, wifi_psk: &str) -> anyhow::Result<()> {
+    let mut nvs = open_nvs()?;
+    nvs.set_str("wifi_ssid", wifi_ssid)?;
+    nvs.set_str("wifi_psk", wifi_psk)?;
+    info!("WiFi credentials saved to NVS");
+    Ok(())
+}
+
+// Store auto-open thresholds set by POST /threshold
+pub fn save_thresholds(max_rssi: i8, min_rssi: i8) -> anyhow::Result<()> {
+    let mut nvs = open_nvs()?;
+    nvs.set_i8("max_rssi", max_rssi)?;
+    nvs.set_i8("min_rssi", min_rssi)?;
+    info!("Thresholds saved to NVS");
+    Ok(())
+}
+
+// Remove stored settings, compiled values are used after reboot.
+// Returns the keys which were stored
+pub fn erase() -> anyhow::Result<Vec<&'static str>> {
+    let mut nvs = open_nvs()?;
+    let mut erased = Vec::new();
+    for &key in NVS_KEYS {
+        if nvs.remove(key)? {
+            info!("{} erased from NVS", key);
+            erased.push(key);
+        }
+    }
+    Ok(erased)
+}
@@ -0,0 +1,183 @@
+// Optional HTTP server for tuning the auto-open thresholds at the parking spot with a phone.
+// GET /threshold returns max_rssi, min_rssi and the current RSSI, POST /threshold changes
+// the thresholds (gate_token required if set), they apply at once and are stored to NVS.
+// GET / is a small page doing both. POST /identify shows the LED rainbow to find the unit.
+// The server runs while WiFi is connected.
+use embedded_svc::{
+    http::{server::Request, Method},
+    io::Write,
+};
+use esp_idf_svc::{
+    hal::io::EspIOError,
+    http::server::{Configuration as HttpServerConfiguration, EspHttpConnection, EspHttpServer},
+};
+use gate_logic::http::{constant_time_eq, form_field};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::sync::{
+    atomic::{AtomicI8, Ordering},
+    Arc,
+};
+
+use crate::settings::{self, Settings};
+use crate::web::read_body;
+use crate::{config, led};
+
+#[derive(Clone, Copy)]
+pub struct Thresholds {
+    pub max_rssi: i8,
+    pub min_rssi: i8,
+}
+
+// Last RSSI of the poll loop, 0 - not connected yet
+static RSSI: AtomicI8 = AtomicI8::new(0);
+
+lazy_static! {
+    static ref THRESHOLDS: Arc<Mutex<Thresholds>> = Arc::new(Mutex::new(Thresholds {
+        max_rssi: config().max_rssi,
+        min_rssi: config().min_rssi,
+    }));
+}
+
+// Take the thresholds of loaded settings
+pub fn init(settings: &Settings) {
+    *THRESHOLDS.clone().lock() = Thresholds {
+        max_rssi: settings.max_rssi,
+        min_rssi: settings.min_rssi,
+    };
+}
+
+pub fn current() -> Thresholds {
+    *THRESHOLDS.clone().lock()
+}
+
+pub fn set_rssi(rssi: i8) {
+    RSSI.store(rssi, Ordering::Relaxed);
+}
+
+// Server is stopped when the returned value is dropped
+pub fn start() -> anyhow::Result<EspHttpServer<'static>> {
+    let mut server = EspHttpServer::new(&HttpServerConfiguration {
+        // Four handlers, fewer slots than the default saves RAM
+        max_uri_handlers: 4,
+        ..Default::default()
+    })?;
+    server.fn_handler(
+        "/",
+        Method::Get,
+        |request| -> core::result::Result<(), EspIOError> {
+            let mut response = request.into_ok_response()?;
+            response.write_all(include_str!("threshold.html").as_bytes())?;
+            Ok(())
+        },
+    )?;
+    server.fn_handler(
+        "/threshold",
+        Method::Get,
+        |request| -> core::result::Result<(), EspIOError> {
+            json_response(request, 200, "OK", &thresholds_json())
+        },
+    )?;
+    server.fn_handler(
+        "/threshold",
+        Method::Post,
+        |mut request| -> core::result::Result<(), EspIOError> {
+            let body = read_body(&mut request, 128)?;
+            if !is_authorized(&request, &body) {
+                warn!("Threshold change rejected: wrong or missing token");
+                return json_response(request, 401, "Unauthorized", "{\"error\":\"unauthorized\"}");
+            }
+            let old = current();
+            let parse = |name: &str, old: i8| match form_field(&body, name) {
+                None => Some(old),
+                Some(value) => value
+                    .trim()
+                    .parse::<i8>()
+                    .ok()
+                    .filter(|v| (-127..=-1).contains(v)),
+            };
+            let (Some(max_rssi), Some(min_rssi)) = (
+                parse("max_rssi", old.max_rssi),
+                parse("min_rssi", old.min_rssi),
+            ) else {
+                return json_response(
+                    request,
+                    400,
+                    "Bad Request",
+                    "{\"error\":\"max_rssi and min_rssi are -127..-1\"}",
+                );
+            };
+            if min_rssi <= max_rssi {
+                return json_response(
+                    request,
+                    400,
+                    "Bad Request",
+                    "{\"error\":\"min_rssi has to be above max_rssi\"}",
+                );
+            }
+            if let Err(e) = settings::save_thresholds(max_rssi, min_rssi) {
+                error!("Can not save thresholds to NVS: {}", e);
+                return json_response(request, 500, "NVS Error", "{\"error\":\"nvs\"}");
+            }
+            *THRESHOLDS.clone().lock() = Thresholds { max_rssi, min_rssi };
+            info!(
+                "Thresholds changed: max_rssi {} -> {}, min_rssi {} -> {}",
+                old.max_rssi, max_rssi, old.min_rssi, min_rssi
+            );
+            json_response(request, 200, "OK", &thresholds_json())
+        },
+    )?;
+    server.fn_handler(
+        "/identify",
+        Method::Post,
+        |request| -> core::result::Result<(), EspIOError> {
+            info!("Identifying");
+            led::identify();
+            json_response(request, 200, "OK", "{\"identify\":true}")
+        },
+    )?;
+    info!("Threshold server started");
+    Ok(server)
+}
+
+// {"max_rssi":-80,"min_rssi":-70,"rssi":-75}, rssi is null before the first sample
+fn thresholds_json() -> String {
+    let thresholds = current();
+    let rssi = match RSSI.load(Ordering::Relaxed) {
+        0 => "null".to_string(),
+        rssi => rssi.to_string(),
+    };
+    format!(
+        "{{\"max_rssi\":{},\"min_rssi\":{},\"rssi\":{}}}",
+        thresholds.max_rssi, thresholds.min_rssi, rssi
+    )
+}
+
+fn json_response(
+    request: Request<&mut EspHttpConnection>,
+    status: u16,
+    message: &str,
+    json: &str,
+) -> core::result::Result<(), EspIOError> {
+    let mut response = request.into_response(
+        status,
+        Some(message),
+        &[("Content-Type", "application/json")],
+    )?;
+    response.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+// gate_token in X-Gate-Token header or token form field, empty gate_token disables the check
+fn is_authorized(request: &Request<&mut EspHttpConnection>, body: &str) -> bool {
+    let gate_token = config().gate_token;
+    if gate_token.is_empty() {
+        return true;
+    }
+    let token = request
+        .header("X-Gate-Token")
+        .map(str::to_string)
+        .or_else(|| form_field(body, "token"));
+    token.is_some_and(|token| constant_time_eq(token.as_bytes(), gate_token.as_bytes()))
+}
@@ -0,0 +1,64 @@
+use gate_logic::urls::{normalize, ping_url, with_port};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+
+use crate::config;
+
+// GateServer command URLs
+pub struct GateUrls {
+    pub open: String,
+    pub sbs: String,
+    pub close: String,
+    pub status: String,
+    // Heartbeat, next to the status URL
+    pub ping: String,
+}
+
+lazy_static! {
+    /// Effective gate URLs, built from gate_host or taken from gate_*_url
+    pub static ref GATE_URLS: GateUrls = GateUrls::from_config();
+}
+
+impl GateUrls {
+    // With gate_host set, URLs are gate_host plus fixed GateServer paths,
+    // otherwise the full gate_*_url values are used after normalization
+    fn from_config() -> GateUrls {
+        if !config().gate_host.is_empty() {
+            let base = normalized("gate_host", config().gate_host);
+            let base = with_port(base.trim_end_matches('/'), config().http_port);
+            info!("Gate URLs built from {}", base);
+            return GateUrls {
+                open: format!("{}/gate_open", base),
+                sbs: format!("{}/gate_sbs", base),
+                close: format!("{}/gate_close", base),
+                status: format!("{}/gate_status", base),
+                ping: format!("{}/ping", base),
+            };
+        }
+        let status = normalized("gate_status_url", config().gate_status_url);
+        let ping = ping_url(&status);
+        GateUrls {
+            open: normalized("gate_open_url", config().gate_open_url),
+            sbs: normalized("gate_sbs_url", config().gate_sbs_url),
+            close: normalized("gate_close_url", config().gate_close_url),
+            status,
+            ping,
+        }
+    }
+}
+
+// Normalized URL, a malformed one is logged and used as is
+fn normalized(name: &str, url: &str) -> String {
+    match normalize(url) {
+        Ok(normalized) => {
+            if normalized != url {
+                warn!("{} {:?} corrected to {:?}", name, url, normalized);
+            }
+            normalized
+        }
+        Err(reason) => {
+            error!("{} {:?} is malformed: {}", name, url, reason);
+            url.to_string()
+        }
+    }
+}
@@ -0,0 +1,92 @@
+// Startup sanity check of the compiled config, so a typo in cfg.toml can not make the gate unsafe.
+// Out of range values are clamped with a warning, dangerous behaviors are disabled with an error.
+use core::fmt::Display;
+use log::{error, warn};
+
+use crate::Config;
+
+// Threshold which never opens the gate: RSSI is never below it
+pub const AUTO_OPEN_DISABLED: i8 = i8::MIN;
+
+impl Config {
+    pub fn validate(mut self) -> Self {
+        if self.max_rssi >= 0 {
+            error!(
+                "max_rssi {} would open the gate on every connection, auto-open disabled",
+                self.max_rssi
+            );
+            self.max_rssi = AUTO_OPEN_DISABLED;
+        }
+        self.min_rssi = clamp("min_rssi", self.min_rssi, -127, -1);
+        if self.min_rssi <= self.max_rssi && self.max_rssi != AUTO_OPEN_DISABLED {
+            warn!(
+                "min_rssi {} is not above max_rssi {}, the gate may open again while standing at the edge of coverage",
+                self.min_rssi, self.max_rssi
+            );
+        }
+        if self.hidden_ssid_channel != 0 {
+            self.hidden_ssid_channel =
+                clamp("hidden_ssid_channel", self.hidden_ssid_channel, 1, 13);
+        }
+        self.approach_dwell_ms = clamp("approach_dwell_ms", self.approach_dwell_ms, 0, 60000);
+        self.rssi_window = clamp("rssi_window", self.rssi_window, 1, 20);
+        if self.open_distance_m != 0.0 {
+            self.open_distance_m = clamp("open_distance_m", self.open_distance_m, 1.0, 1000.0);
+        }
+        if self.departure_rssi != 0 {
+            self.departure_rssi = clamp("departure_rssi", self.departure_rssi, -120, -20);
+        }
+        self.rssi_at_1m = clamp("rssi_at_1m", self.rssi_at_1m, -100, -1);
+        self.path_loss_exponent = clamp("path_loss_exponent", self.path_loss_exponent, 1.0, 6.0);
+        if self.http_port == 0 {
+            warn!("http_port 0 is out of range 1..65535, using 80");
+            self.http_port = 80;
+        }
+        if self.long_press_ms > 0 {
+            // Shorter holds would make every press a long one, which opens the gate
+            self.long_press_ms = clamp("long_press_ms", self.long_press_ms, 300, 10000);
+        }
+        self.open_confirm_secs = clamp("open_confirm_secs", self.open_confirm_secs, 1, 300);
+        self.ack_error_ms = clamp("ack_error_ms", self.ack_error_ms, 0, 10000);
+        self.ack_success_ms = clamp("ack_success_ms", self.ack_success_ms, 0, 1000);
+        self.auto_open_cooldown_secs = clamp(
+            "auto_open_cooldown_secs",
+            self.auto_open_cooldown_secs,
+            0,
+            86400,
+        );
+        self.http_timeout_ms = clamp("http_timeout_ms", self.http_timeout_ms, 500, 30000);
+        self.http_retries = clamp("http_retries", self.http_retries, 1, 10);
+        self.scan_backoff_max_ms = clamp(
+            "scan_backoff_max_ms",
+            self.scan_backoff_max_ms,
+            1000,
+            600000,
+        );
+        self.provision_timeout_secs = clamp(
+            "provision_timeout_secs",
+            self.provision_timeout_secs,
+            60,
+            3600,
+        );
+        if self.factory_reset_secs > 0 {
+            // Shorter holds could be an ordinary press right after power on
+            self.factory_reset_secs = clamp("factory_reset_secs", self.factory_reset_secs, 3, 60);
+        }
+        self.sleep_secs = clamp("sleep_secs", self.sleep_secs, 0, 3600);
+        self
+    }
+}
+
+// Value limited to min..=max, a change is logged. Values which do not compare (NaN) become min
+fn clamp<T: PartialOrd + Copy + Display>(name: &str, value: T, min: T, max: T) -> T {
+    if value >= min && value <= max {
+        return value;
+    }
+    let clamped = if value > max { max } else { min };
+    warn!(
+        "{} {} is out of range {}..{}, using {}",
+        name, value, min, max, clamped
+    );
+    clamped
+}
@@ -0,0 +1,12 @@
+use embedded_svc::{http::server::Request, utils::io};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+
+// Read small request body (form) into a string, truncated to buffer size
+pub fn read_body(
+    request: &mut Request<&mut EspHttpConnection>,
+    max_len: usize,
+) -> Result<String, EspIOError> {
+    let mut buf = vec![0u8; max_len];
+    let bytes_read = io::try_read_full(request, &mut buf).map_err(|e| e.0)?;
+    Ok(String::from_utf8_lossy(&buf[..bytes_read]).into_owned())
+}
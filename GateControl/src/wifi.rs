@@ -1,83 +1,291 @@
-use esp_idf_hal::{delay::FreeRtos, peripheral::Peripheral};
+use esp_idf_hal::{delay::FreeRtos, peripheral::Peripheral, reset};
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
-    nvs::*,
-    wifi::{AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi},
+    netif::{EspNetif, NetifStack},
+    sys::esp_random,
+    wifi::{BlockingWifi, ClientConfiguration, Configuration, EspWifi, WifiDriver},
 };
+use lazy_static::lazy_static;
+use log::warn;
+use parking_lot::Mutex;
+use std::sync::Arc;
 
-use crate::PERIPHERALS;
+use crate::{config, hardware, wifi_setup};
 
+// Delay after the first missed scan, doubled for the next ones
+const SCAN_RETRY_MS: u32 = 1000;
+
+// Access point of the last successful connection, for fast_connect
+#[derive(Clone)]
+struct KnownAp {
+    ssid: String,
+    channel: u8,
+}
+
+lazy_static! {
+    /// Kept across reconnects, lost on reboot
+    static ref LAST_AP: Arc<Mutex<Option<KnownAp>>> = Arc::new(Mutex::new(None));
+}
+
+// WiFi networks from comma separated SSID and PSK lists.
+// A single PSK is used for all SSIDs, otherwise PSKs are paired by position.
+pub fn networks(wifi_ssids: &str, wifi_psks: &str) -> Vec<(String, String)> {
+    let psks: Vec<&str> = wifi_psks.split(',').map(str::trim).collect();
+    wifi_ssids
+        .split(',')
+        .map(str::trim)
+        .filter(|ssid| !ssid.is_empty())
+        .enumerate()
+        .map(|(i, ssid)| {
+            let psk = if psks.len() == 1 {
+                psks[0]
+            } else {
+                psks.get(i).copied().unwrap_or("")
+            };
+            (ssid.to_string(), psk.to_string())
+        })
+        .collect()
+}
+
+// Connect to the strongest of the configured access points, returned with its scan RSSI.
+// The scan RSSI only chooses the access point, approach decisions use the live AP info reading.
+// With hidden_ssid the scan can not see our access points, so they are connected directly
+// in configuration order and the RSSI is that of the AP info.
+// None - no one found in max_missed_scans scans (0 - scan forever)
 pub fn connect_wifi(
-    wifi_ssid: &str,
-    wifi_psk: &str,
-) -> anyhow::Result<(Box<EspWifi<'static>>, i8)> {
+    networks: &[(String, String)],
+    max_missed_scans: u32,
+) -> anyhow::Result<Option<(Box<EspWifi<'static>>, i8)>> {
     use log::info;
 
     let mut last_rssi: Option<i8> = None;
-    let auth_method = if wifi_psk.is_empty() {
-        info!("Wifi password is empty");
-        AuthMethod::None
-    } else {
-        AuthMethod::WPA2Personal
-    };
+    let mut missed_scans = 0;
+    // Missed scans in a row, for the retry backoff
+    let mut backoff_step = 0;
+    let mut failed_attempts = 0;
+    let ssids = networks
+        .iter()
+        .map(|(ssid, _)| ssid.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
 
-    let _nvs_default_partition: EspNvsPartition<NvsDefault> = EspDefaultNvsPartition::take()?;
-    let peripherals = PERIPHERALS.clone();
+    let _nvs_default_partition = hardware().nvs_partition.clone();
+    let peripherals = hardware().peripherals.clone();
     let mut peripherals = peripherals.lock();
     let modem = unsafe { peripherals.modem.clone_unchecked() };
     let sysloop = EspSystemEventLoop::take()?;
-    let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), None)?;
+    let driver = WifiDriver::new(modem, sysloop.clone(), None)?;
+    let sta_netif = wifi_setup::sta_netif(wifi_setup::static_ip_settings(
+        config().static_ip,
+        config().gateway,
+        config().netmask,
+    ))?;
+    let mut esp_wifi = EspWifi::wrap_all(driver, sta_netif, EspNetif::new(NetifStack::Ap)?)?;
     let mut wifi = BlockingWifi::wrap(&mut esp_wifi, sysloop)?;
     wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
     wifi.start()?;
+    if let Some(rssi) = fast_connect(&mut wifi, networks) {
+        return Ok(Some((Box::new(esp_wifi), rssi)));
+    }
     'wifi_loop: loop {
-        let ap_infos = wifi.scan()?;
-        let ours = ap_infos.into_iter().find(|a| a.ssid == wifi_ssid);
+        if config().hidden_ssid {
+            if let Some(rssi) = hidden_connect(&mut wifi, networks) {
+                break 'wifi_loop Ok(Some((Box::new(esp_wifi), rssi)));
+            }
+        }
+        // Hidden access points are not listed by name, a failed direct connect counts as a miss
+        let ap_infos = if config().hidden_ssid {
+            Vec::new()
+        } else {
+            wifi.scan()?
+        };
+        let ours = ap_infos
+            .into_iter()
+            .filter_map(|a| {
+                let (ssid, psk) = networks.iter().find(|(ssid, _)| a.ssid == ssid.as_str())?;
+                Some((ssid, psk, a.channel, a.signal_strength))
+            })
+            .max_by_key(|ours| ours.3);
 
-        let channel = if let Some(ours) = ours {
+        let (wifi_ssid, wifi_psk, channel) = if let Some(ours) = ours {
             info!(
                 "Found configured access point {} on channel {} with signal strength {}",
-                wifi_ssid, ours.channel, ours.signal_strength
+                ours.0, ours.2, ours.3
             );
-            (ours.channel, ours.signal_strength)
+            if last_rssi.is_none() {
+                last_rssi = Some(ours.3);
+            }
+            backoff_step = 0;
+            (ours.0, ours.1, ours.2)
         } else {
+            let delay_ms = scan_retry_delay_ms(backoff_step);
+            backoff_step += 1;
             info!(
-                "Configured access point {} not found during scanning, delay one seconds and retry",
-                wifi_ssid
+                "Configured access points {} not found during scanning, retry in {} ms",
+                ssids, delay_ms
             );
             last_rssi = None;
-            FreeRtos::delay_ms(1000);
+            missed_scans += 1;
+            if max_missed_scans > 0 && missed_scans >= max_missed_scans {
+                warn!(
+                    "Configured access points {} not found in {} scans",
+                    ssids, missed_scans
+                );
+                break 'wifi_loop Ok(None);
+            }
+            count_failed_attempt(&mut failed_attempts);
+            FreeRtos::delay_ms(delay_ms);
             continue 'wifi_loop;
         };
 
-        if last_rssi.is_none() {
-            last_rssi = Some(channel.1);
-        }
-
-        wifi.set_configuration(&Configuration::Client(ClientConfiguration {
-            ssid: wifi_ssid
-                .try_into()
-                .expect("Could not parse the given SSID into WiFi config"),
-            password: wifi_psk
-                .try_into()
-                .expect("Could not parse the given password into WiFi config"),
-            channel: Some(channel.0),
-            auth_method,
-            ..Default::default()
-        }))?;
+        wifi.set_configuration(&client_configuration(wifi_ssid, wifi_psk, Some(channel)))?;
 
         info!("Connecting wifi...");
         if wifi.connect() != Ok(()) {
+            count_failed_attempt(&mut failed_attempts);
             continue 'wifi_loop;
         }
 
         info!("Waiting for DHCP lease...");
         if wifi.wait_netif_up() != Ok(()) {
+            count_failed_attempt(&mut failed_attempts);
             continue 'wifi_loop;
         }
         info!("Get IP info");
         let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
         info!("Wifi DHCP info: {:?}", ip_info);
-        break 'wifi_loop Ok((Box::new(esp_wifi), last_rssi.unwrap()));
+        *LAST_AP.clone().lock() = Some(KnownAp {
+            ssid: wifi_ssid.clone(),
+            channel,
+        });
+        break 'wifi_loop Ok(Some((Box::new(esp_wifi), last_rssi.unwrap())));
+    }
+}
+
+// Count a failed scan or connect, reboot after max_connect_attempts of them (0 - never).
+// A full reset sometimes clears a wedged WiFi stack that retrying can not
+fn count_failed_attempt(failed_attempts: &mut u32) {
+    *failed_attempts += 1;
+    let max_attempts = config().max_connect_attempts;
+    if max_attempts == 0 {
+        return;
     }
+    log::info!(
+        "WiFi connect attempt {} of {} failed",
+        failed_attempts,
+        max_attempts
+    );
+    if *failed_attempts >= max_attempts {
+        warn!(
+            "WiFi not connected in {} attempts, rebooting",
+            failed_attempts
+        );
+        reset::restart();
+    }
+}
+
+// Delay before the next scan: it doubles with each missed scan in a row up to scan_backoff_max_ms
+// and is randomized to 50..100%, so devices powered up together do not scan in lockstep
+fn scan_retry_delay_ms(backoff_step: u32) -> u32 {
+    let max_ms = config().scan_backoff_max_ms.max(SCAN_RETRY_MS);
+    let delay_ms = SCAN_RETRY_MS
+        .saturating_mul(1 << backoff_step.min(16))
+        .min(max_ms);
+    let jitter_ms = unsafe { esp_random() } % (delay_ms / 2 + 1);
+    delay_ms - jitter_ms
+}
+
+// Connect to the access point of the previous connection on its channel without scanning.
+// None - fast_connect is off, no previous connection or it failed, so scanning is needed
+fn fast_connect(
+    wifi: &mut BlockingWifi<&mut EspWifi<'static>>,
+    networks: &[(String, String)],
+) -> Option<i8> {
+    if !config().fast_connect {
+        return None;
+    }
+    let known = LAST_AP.clone().lock().clone()?;
+    // Configured networks may have changed since, e.g. by provisioning
+    let (wifi_ssid, wifi_psk) = networks.iter().find(|(ssid, _)| *ssid == known.ssid)?;
+    log::info!(
+        "Connecting {} on channel {} without scanning",
+        wifi_ssid,
+        known.channel
+    );
+    let connected = wifi
+        .set_configuration(&client_configuration(
+            wifi_ssid,
+            wifi_psk,
+            Some(known.channel),
+        ))
+        .and_then(|_| wifi.connect())
+        .and_then(|_| wifi.wait_netif_up())
+        .and_then(|_| wifi.wifi_mut().driver_mut().get_ap_info());
+    match connected {
+        Ok(ap_info) => {
+            log::info!("Connected with signal strength {}", ap_info.signal_strength);
+            Some(ap_info.signal_strength)
+        }
+        Err(e) => {
+            warn!("Fast connect failed, scanning: {}", e);
+            let _ = wifi.disconnect();
+            None
+        }
+    }
+}
+
+// Connect to the configured access points one by one by name, on hidden_ssid_channel or,
+// with 0, on any channel found by the driver probing for the SSID. Returns the AP info RSSI,
+// None - none of them connected
+fn hidden_connect(
+    wifi: &mut BlockingWifi<&mut EspWifi<'static>>,
+    networks: &[(String, String)],
+) -> Option<i8> {
+    let channel = match config().hidden_ssid_channel {
+        0 => None,
+        channel => Some(channel),
+    };
+    for (wifi_ssid, wifi_psk) in networks {
+        log::info!("Connecting hidden access point {}", wifi_ssid);
+        let connected = wifi
+            .set_configuration(&client_configuration(wifi_ssid, wifi_psk, channel))
+            .and_then(|_| wifi.connect())
+            .and_then(|_| wifi.wait_netif_up())
+            .and_then(|_| wifi.wifi_mut().driver_mut().get_ap_info());
+        match connected {
+            Ok(ap_info) => {
+                log::info!(
+                    "Connected {} on channel {} with signal strength {}",
+                    wifi_ssid,
+                    ap_info.channel,
+                    ap_info.signal_strength
+                );
+                *LAST_AP.clone().lock() = Some(KnownAp {
+                    ssid: wifi_ssid.clone(),
+                    channel: ap_info.channel,
+                });
+                return Some(ap_info.signal_strength);
+            }
+            Err(e) => {
+                warn!("Hidden access point {} not connected: {}", wifi_ssid, e);
+                let _ = wifi.disconnect();
+            }
+        }
+    }
+    None
+}
+
+// Station configuration, channel None - the driver looks for the SSID on all channels
+fn client_configuration(wifi_ssid: &str, wifi_psk: &str, channel: Option<u8>) -> Configuration {
+    Configuration::Client(ClientConfiguration {
+        ssid: wifi_ssid
+            .try_into()
+            .expect("Could not parse the given SSID into WiFi config"),
+        password: wifi_psk
+            .try_into()
+            .expect("Could not parse the given password into WiFi config"),
+        channel,
+        auth_method: wifi_setup::auth_method(config().auth_method, wifi_psk),
+        ..Default::default()
+    })
 }
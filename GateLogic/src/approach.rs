@@ -0,0 +1,252 @@
+// Auto-open decisions of GateControl from the access point signal strength, free of hardware access
+// and config: the thresholds are passed in.
+// A connection with RSSI below max_rssi means the car approaches from afar and opens
+// the gate once, then RSSI has to rise to min_rssi (car near the house) to arm it again.
+// With open_distance_m the far side is decided by the distance estimated from RSSI instead.
+// Close on departure is the reverse: RSSI falling below departure_rssi after the car was near.
+// The far side is decided by the average of the last rssi_window samples, so a momentary dip
+// from fading or multipath does not open the gate.
+use core::fmt;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Log-distance path loss model of the access point signal, for the distance estimation
+#[derive(Clone, Copy)]
+pub struct PathLoss {
+    // RSSI one meter away from the access point
+    pub rssi_at_1m: i8,
+    // 2 - open space, 2.7..4 - with obstacles
+    pub exponent: f32,
+}
+
+impl PathLoss {
+    // Distance to the access point in meters: rssi = rssi_at_1m - 10 * exponent * log10(distance)
+    pub fn distance(self, rssi: i8) -> f32 {
+        let exponent = self.exponent.max(1.0);
+        10f32.powf((self.rssi_at_1m as f32 - rssi as f32) / (10.0 * exponent))
+    }
+}
+
+// Where the car counts as approaching from afar
+#[derive(Clone, Copy)]
+pub enum Threshold {
+    // Signal strength below this RSSI
+    Rssi(i8),
+    // Estimated distance beyond this many meters
+    Distance(f32, PathLoss),
+}
+
+impl Threshold {
+    // open_distance_m if above 0, max_rssi otherwise
+    pub fn new(max_rssi: i8, open_distance_m: f32, path_loss: PathLoss) -> Self {
+        if open_distance_m > 0.0 {
+            Threshold::Distance(open_distance_m, path_loss)
+        } else {
+            Threshold::Rssi(max_rssi)
+        }
+    }
+
+    pub fn far(self, rssi: i8) -> bool {
+        match self {
+            Threshold::Rssi(max_rssi) => rssi < max_rssi,
+            Threshold::Distance(open_distance_m, path_loss) => {
+                path_loss.distance(rssi) > open_distance_m
+            }
+        }
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threshold::Rssi(max_rssi) => write!(f, "rssi {}", max_rssi),
+            Threshold::Distance(open_distance_m, _) => write!(f, "{:.1} m", open_distance_m),
+        }
+    }
+}
+
+// Moving average of the last RSSI samples of a connection
+pub struct RssiWindow {
+    samples: VecDeque<i8>,
+    size: usize,
+}
+
+impl RssiWindow {
+    // Window of size samples, starting with the first one of the connection
+    pub fn new(size: u8, first: i8) -> Self {
+        let size = size.max(1) as usize;
+        let mut samples = VecDeque::with_capacity(size);
+        samples.push_back(first);
+        RssiWindow { samples, size }
+    }
+
+    // Add a sample, dropping the oldest one once the window is full. Returns the new average
+    pub fn push(&mut self, rssi: i8) -> i8 {
+        if self.samples.len() == self.size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rssi);
+        self.average()
+    }
+
+    // Average of the samples, rounded to the nearest
+    pub fn average(&self) -> i8 {
+        let sum: i32 = self.samples.iter().map(|&rssi| rssi as i32).sum();
+        (sum as f32 / self.samples.len() as f32).round() as i8
+    }
+}
+
+// Auto-open is due on a connection with signal strength rssi
+pub fn should_open(armed: bool, rssi: i8, threshold: Threshold) -> bool {
+    armed && threshold.far(rssi)
+}
+
+// Armed state after a signal strength sample
+pub fn rearm(armed: bool, rssi: i8, min_rssi: i8) -> bool {
+    armed || rssi >= min_rssi
+}
+
+// Weak signal dwell before auto-open. Started by a weak connection and cancelled once the car
+// is no longer far by the threshold, so a fob carried past the edge of coverage does not open the gate
+#[derive(Default)]
+pub struct Dwell {
+    started: Option<Instant>,
+}
+
+impl Dwell {
+    pub fn start(&mut self) {
+        self.started = Some(Instant::now());
+    }
+
+    // Waiting for the dwell to complete or be cancelled
+    pub fn pending(&self) -> bool {
+        self.started.is_some()
+    }
+
+    // Signal strength sample, true - the car stayed far by the threshold for dwell_ms and the gate
+    // should be opened. Dwell ends either way then
+    pub fn sample(&mut self, rssi: i8, threshold: Threshold, dwell_ms: u32) -> bool {
+        let Some(started) = self.started else {
+            return false;
+        };
+        if !threshold.far(rssi) {
+            self.started = None;
+            return false;
+        }
+        if started.elapsed() < Duration::from_millis(dwell_ms as u64) {
+            return false;
+        }
+        self.started = None;
+        true
+    }
+}
+
+// RSSI has to rise this much above departure_rssi to arm close on departure
+pub const DEPARTURE_HYSTERESIS: i8 = 10;
+
+// Close on departure, once per departure. Armed while the car is near, RSSI at least
+// DEPARTURE_HYSTERESIS above departure_rssi, fires when RSSI falls below departure_rssi.
+// Starts disarmed on each connection, so arriving with a weak signal never closes the gate
+#[derive(Default)]
+pub struct Departure {
+    armed: bool,
+}
+
+impl Departure {
+    // Signal strength sample, true - the car is leaving and the gate should be closed
+    pub fn sample(&mut self, rssi: i8, departure_rssi: i8) -> bool {
+        if rssi >= departure_rssi.saturating_add(DEPARTURE_HYSTERESIS) {
+            self.armed = true;
+            return false;
+        }
+        if self.armed && rssi < departure_rssi {
+            self.armed = false;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH_LOSS: PathLoss = PathLoss {
+        rssi_at_1m: -45,
+        exponent: 2.0,
+    };
+
+    #[test]
+    fn rssi_threshold() {
+        let threshold = Threshold::new(-80, 0.0, PATH_LOSS);
+        assert!(threshold.far(-81));
+        assert!(!threshold.far(-80));
+        assert!(!threshold.far(-60));
+    }
+
+    #[test]
+    fn distance_threshold() {
+        // -45 - 20 * log10(10) = -65 at 10 m
+        assert!((PATH_LOSS.distance(-65) - 10.0).abs() < 0.01);
+        let threshold = Threshold::new(-80, 20.0, PATH_LOSS);
+        assert!(threshold.far(-75));
+        assert!(!threshold.far(-65));
+    }
+
+    #[test]
+    fn opens_once_until_rearmed() {
+        let threshold = Threshold::new(-80, 0.0, PATH_LOSS);
+        assert!(should_open(true, -85, threshold));
+        assert!(!should_open(false, -85, threshold));
+        assert!(!rearm(false, -75, -70));
+        assert!(rearm(false, -70, -70));
+    }
+
+    #[test]
+    fn rssi_window_averages_last_samples() {
+        let mut window = RssiWindow::new(3, -60);
+        assert_eq!(window.push(-70), -65);
+        assert_eq!(window.push(-80), -70);
+        // -60 drops out
+        assert_eq!(window.push(-90), -80);
+        assert_eq!(window.average(), -80);
+    }
+
+    #[test]
+    fn rssi_window_of_one_follows_samples() {
+        let mut window = RssiWindow::new(1, -60);
+        assert_eq!(window.push(-90), -90);
+        assert_eq!(RssiWindow::new(0, -50).push(-70), -70);
+    }
+
+    #[test]
+    fn dwell_without_delay_opens_at_once() {
+        let threshold = Threshold::new(-80, 0.0, PATH_LOSS);
+        let mut dwell = Dwell::default();
+        assert!(!dwell.sample(-85, threshold, 0));
+        dwell.start();
+        assert!(dwell.sample(-85, threshold, 0));
+        assert!(!dwell.pending());
+    }
+
+    #[test]
+    fn dwell_cancelled_when_no_longer_far() {
+        let threshold = Threshold::new(-80, 0.0, PATH_LOSS);
+        let mut dwell = Dwell::default();
+        dwell.start();
+        assert!(!dwell.sample(-85, threshold, 60000));
+        assert!(dwell.pending());
+        assert!(!dwell.sample(-70, threshold, 60000));
+        assert!(!dwell.pending());
+    }
+
+    #[test]
+    fn departure_fires_once_after_being_near() {
+        let mut departure = Departure::default();
+        // Arriving with a weak signal never closes
+        assert!(!departure.sample(-80, -75));
+        assert!(!departure.sample(-60, -75));
+        assert!(departure.sample(-80, -75));
+        assert!(!departure.sample(-80, -75));
+    }
+}
@@ -0,0 +1,151 @@
+// Access decision of GateServer requests: a shared-secret token from the X-Gate-Token header or
+// the token query parameter, or HTTP Basic credentials, either valid one grants access.
+// GateServer passes the request headers and its configured secrets, and answers 401 on false.
+use crate::http::{basic_credentials, constant_time_eq, query_param};
+
+// Configured secrets: gate_token of the settings and basic_user:basic_pass of the config.
+// Empty gate_token or basic_user disables that credential
+pub struct Credentials<'a> {
+    pub gate_token: &'a str,
+    pub basic_user: &'a str,
+    pub basic_pass: &'a str,
+}
+
+impl Credentials<'_> {
+    // Either credential is checked once configured
+    pub fn required(&self) -> bool {
+        !self.gate_token.is_empty() || !self.basic_user.is_empty()
+    }
+
+    // Request with these X-Gate-Token and Authorization headers and URI is let in.
+    // Every request is, if no credential is configured
+    pub fn accept(
+        &self,
+        token_header: Option<&str>,
+        uri: &str,
+        authorization: Option<&str>,
+    ) -> bool {
+        if !self.required() {
+            return true;
+        }
+        self.token_valid(token_header, uri) || self.basic_valid(authorization)
+    }
+
+    fn token_valid(&self, token_header: Option<&str>, uri: &str) -> bool {
+        if self.gate_token.is_empty() {
+            return false;
+        }
+        let token = token_header
+            .map(str::to_string)
+            .or_else(|| query_param(uri, "token"));
+        match token {
+            Some(token) => constant_time_eq(token.as_bytes(), self.gate_token.as_bytes()),
+            None => false,
+        }
+    }
+
+    // Authorization: Basic base64(user:pass) matching basic_user and basic_pass
+    fn basic_valid(&self, authorization: Option<&str>) -> bool {
+        if self.basic_user.is_empty() {
+            return false;
+        }
+        let Some(credentials) = authorization.and_then(basic_credentials) else {
+            return false;
+        };
+        let expected = format!("{}:{}", self.basic_user, self.basic_pass);
+        constant_time_eq(&credentials, expected.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: Credentials = Credentials {
+        gate_token: "s3cret+token",
+        basic_user: "",
+        basic_pass: "",
+    };
+    const BASIC: Credentials = Credentials {
+        gate_token: "",
+        basic_user: "gate",
+        basic_pass: "pass",
+    };
+    // Authorization header of gate:pass
+    const GATE_PASS: &str = "Basic Z2F0ZTpwYXNz";
+
+    #[test]
+    fn everyone_accepted_with_auth_disabled() {
+        let open = Credentials {
+            gate_token: "",
+            basic_user: "",
+            basic_pass: "ignored",
+        };
+        assert!(!open.required());
+        assert!(open.accept(None, "/gate_open", None));
+        assert!(open.accept(Some("anything"), "/gate_open?token=x", Some("Basic eDp5")));
+    }
+
+    #[test]
+    fn missing_token_rejected() {
+        assert!(TOKEN.required());
+        assert!(!TOKEN.accept(None, "/gate_open", None));
+        assert!(!TOKEN.accept(Some(""), "/gate_open", None));
+    }
+
+    #[test]
+    fn wrong_token_rejected() {
+        assert!(!TOKEN.accept(Some("s3cret"), "/gate_open", None));
+        assert!(!TOKEN.accept(Some("s3cret+token2"), "/gate_open", None));
+        assert!(!TOKEN.accept(None, "/gate_open?token=s3cret%2Btoken2", None));
+    }
+
+    #[test]
+    fn correct_token_accepted() {
+        assert!(TOKEN.accept(Some("s3cret+token"), "/gate_open", None));
+    }
+
+    #[test]
+    fn query_token_accepted_decoded() {
+        assert!(TOKEN.accept(None, "/gate_open?token=s3cret%2Btoken", None));
+        // Undecoded + is a space
+        assert!(!TOKEN.accept(None, "/gate_open?token=s3cret+token", None));
+    }
+
+    #[test]
+    fn header_token_wins_over_query() {
+        assert!(!TOKEN.accept(Some("wrong"), "/gate_open?token=s3cret%2Btoken", None));
+    }
+
+    #[test]
+    fn basic_credentials_checked() {
+        assert!(BASIC.required());
+        assert!(BASIC.accept(None, "/settings", Some(GATE_PASS)));
+        // gate:wrong
+        assert!(!BASIC.accept(None, "/settings", Some("Basic Z2F0ZTp3cm9uZw==")));
+        assert!(!BASIC.accept(None, "/settings", Some("Bearer Z2F0ZTpwYXNz")));
+        assert!(!BASIC.accept(None, "/settings", None));
+    }
+
+    #[test]
+    fn token_not_accepted_as_basic_and_back() {
+        // Empty gate_token is not a credential, an empty token does not match it
+        let empty_token = Credentials {
+            gate_token: "",
+            ..BASIC
+        };
+        assert!(!empty_token.accept(Some(""), "/gate_open?token=", None));
+        assert!(!TOKEN.accept(None, "/gate_open", Some(GATE_PASS)));
+    }
+
+    #[test]
+    fn either_credential_accepted_when_both_set() {
+        let both = Credentials {
+            gate_token: "s3cret+token",
+            ..BASIC
+        };
+        assert!(both.accept(Some("s3cret+token"), "/gate_open", None));
+        assert!(both.accept(None, "/gate_open", Some(GATE_PASS)));
+        assert!(!both.accept(Some("wrong"), "/gate_open", Some("Basic Z2F0ZTp3cm9uZw==")));
+    }
+}
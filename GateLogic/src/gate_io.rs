@@ -0,0 +1,239 @@
+// Gate hardware as seen by the gate logic: raw limit sensor levels and relay pulses, and the
+// gate status read through it. GateServer implements GateIo on the pins of its gates, the tests
+// on preset sensor levels with pulse counters.
+use crate::gate_state::GateState;
+
+// Pause between sensor samples
+const SAMPLE_PAUSE_MS: u32 = 10;
+
+pub trait GateIo {
+    // Raw level of the gate opened sensor, true - high
+    fn opened_high(&self) -> bool;
+    // Raw level of the gate closed sensor, true - high
+    fn closed_high(&self) -> bool;
+    // Close the open relay contacts for ms milliseconds
+    fn pulse_open(&self, ms: u32);
+    // Close the SBS relay contacts for ms milliseconds
+    fn pulse_sbs(&self, ms: u32);
+    fn pause_ms(&self, ms: u32);
+}
+
+// Gate status from triggered sensors: both - fault, neither - moving
+pub fn status_from_sensors(opened: bool, closed: bool) -> GateState {
+    if opened && closed {
+        GateState::Fault
+    } else if opened {
+        GateState::Open
+    } else if closed {
+        GateState::Closed
+    } else {
+        GateState::Moving
+    }
+}
+
+// Gate status from the limit sensors, each one debounced by the majority of samples.
+// Sensor is triggered by high level, or by low level with active_low.
+// Both sensors are always read, so a failed one is detected as a fault
+pub fn read_status(io: &impl GateIo, samples: u8, active_low: bool) -> GateState {
+    let sample = |level_high: bool| {
+        io.pause_ms(SAMPLE_PAUSE_MS);
+        level_high != active_low
+    };
+    let opened = majority(samples, || sample(io.opened_high()));
+    let closed = majority(samples, || sample(io.closed_high()));
+    status_from_sensors(opened, closed)
+}
+
+// Gate close command on the gate with this status, the status after it is returned.
+// SBS relay is pulsed only when the gate is open, so a closed gate is never opened by mistake
+// and a moving one is not stopped or reversed
+pub fn close(io: &impl GateIo, status: GateState, sbs_pulse_ms: u32) -> GateState {
+    match status {
+        GateState::Open => {
+            io.pulse_sbs(sbs_pulse_ms);
+            GateState::Moving
+        }
+        status => status,
+    }
+}
+
+// Sensor debounce: true only if more than half of the samples are true
+fn majority(samples: u8, mut read: impl FnMut() -> bool) -> bool {
+    let samples = samples.max(1);
+    let high = (0..samples).filter(|_| read()).count();
+    high * 2 > samples as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    // Preset sensor levels and pulse counters, no hardware access
+    #[derive(Default)]
+    pub struct MockGateIo {
+        pub opened_high: Cell<bool>,
+        pub closed_high: Cell<bool>,
+        pub open_pulses: Cell<u32>,
+        pub sbs_pulses: Cell<u32>,
+    }
+
+    impl GateIo for MockGateIo {
+        fn opened_high(&self) -> bool {
+            self.opened_high.get()
+        }
+
+        fn closed_high(&self) -> bool {
+            self.closed_high.get()
+        }
+
+        fn pulse_open(&self, _ms: u32) {
+            self.open_pulses.set(self.open_pulses.get() + 1);
+        }
+
+        fn pulse_sbs(&self, _ms: u32) {
+            self.sbs_pulses.set(self.sbs_pulses.get() + 1);
+        }
+
+        fn pause_ms(&self, _ms: u32) {}
+    }
+
+    fn gate(opened_high: bool, closed_high: bool) -> MockGateIo {
+        let io = MockGateIo::default();
+        io.opened_high.set(opened_high);
+        io.closed_high.set(closed_high);
+        io
+    }
+
+    #[test]
+    fn status_from_sensors_covers_all_combinations() {
+        assert_eq!(status_from_sensors(true, false), GateState::Open);
+        assert_eq!(status_from_sensors(false, true), GateState::Closed);
+        assert_eq!(status_from_sensors(false, false), GateState::Moving);
+        assert_eq!(status_from_sensors(true, true), GateState::Fault);
+    }
+
+    // Samples read in turn from a fixed sequence
+    fn samples(levels: &[bool]) -> impl FnMut() -> bool + '_ {
+        let mut levels = levels.iter();
+        move || *levels.next().unwrap()
+    }
+
+    #[test]
+    fn majority_needs_more_than_half() {
+        assert!(majority(3, samples(&[true, false, true])));
+        assert!(!majority(3, samples(&[false, true, false])));
+        assert!(!majority(4, samples(&[true, false, true, false])));
+        assert!(majority(5, samples(&[true, true, false, true, false])));
+    }
+
+    #[test]
+    fn majority_of_one_or_zero_samples() {
+        assert!(majority(1, samples(&[true])));
+        assert!(!majority(1, samples(&[false])));
+        // Zero samples is read as one
+        assert!(majority(0, samples(&[true])));
+    }
+
+    #[test]
+    fn majority_reads_every_sample() {
+        let mut reads = 0;
+        majority(5, || {
+            reads += 1;
+            true
+        });
+        assert_eq!(reads, 5);
+    }
+
+    #[test]
+    fn read_status_debounces_a_glitch() {
+        // Opened sensor reads low once out of three samples
+        let io = gate(true, false);
+        let reads = Cell::new(0);
+        let glitch = GlitchGateIo {
+            io: &io,
+            reads: &reads,
+        };
+        assert_eq!(read_status(&glitch, 3, false), GateState::Open);
+    }
+
+    // Opened sensor of the inner GateIo reads inverted on the second read
+    struct GlitchGateIo<'a> {
+        io: &'a MockGateIo,
+        reads: &'a Cell<u32>,
+    }
+
+    impl GateIo for GlitchGateIo<'_> {
+        fn opened_high(&self) -> bool {
+            self.reads.set(self.reads.get() + 1);
+            self.io.opened_high() != (self.reads.get() == 2)
+        }
+
+        fn closed_high(&self) -> bool {
+            self.io.closed_high()
+        }
+
+        fn pulse_open(&self, ms: u32) {
+            self.io.pulse_open(ms)
+        }
+
+        fn pulse_sbs(&self, ms: u32) {
+            self.io.pulse_sbs(ms)
+        }
+
+        fn pause_ms(&self, ms: u32) {
+            self.io.pause_ms(ms)
+        }
+    }
+
+    #[test]
+    fn read_status_active_high() {
+        assert_eq!(read_status(&gate(true, false), 3, false), GateState::Open);
+        assert_eq!(read_status(&gate(false, true), 3, false), GateState::Closed);
+        assert_eq!(
+            read_status(&gate(false, false), 3, false),
+            GateState::Moving
+        );
+        assert_eq!(read_status(&gate(true, true), 3, false), GateState::Fault);
+    }
+
+    #[test]
+    fn read_status_active_low() {
+        assert_eq!(read_status(&gate(false, true), 3, true), GateState::Open);
+        assert_eq!(read_status(&gate(true, false), 3, true), GateState::Closed);
+        assert_eq!(read_status(&gate(true, true), 3, true), GateState::Moving);
+        assert_eq!(read_status(&gate(false, false), 3, true), GateState::Fault);
+    }
+
+    #[test]
+    fn close_pulses_open_gate_once() {
+        let io = gate(true, false);
+        assert_eq!(close(&io, GateState::Open, 500), GateState::Moving);
+        assert_eq!(io.sbs_pulses.get(), 1);
+        assert_eq!(io.open_pulses.get(), 0);
+    }
+
+    #[test]
+    fn close_no_pulse_when_already_closed() {
+        let io = gate(false, true);
+        assert_eq!(close(&io, GateState::Closed, 500), GateState::Closed);
+        assert_eq!(io.sbs_pulses.get(), 0);
+        assert_eq!(io.open_pulses.get(), 0);
+    }
+
+    #[test]
+    fn close_no_pulse_when_moving_or_fault() {
+        let io = gate(false, false);
+        assert_eq!(close(&io, GateState::Moving, 500), GateState::Moving);
+        assert_eq!(close(&io, GateState::Fault, 500), GateState::Fault);
+        assert_eq!(io.sbs_pulses.get(), 0);
+    }
+
+    #[test]
+    fn read_status_does_not_pulse_relays() {
+        let io = gate(true, false);
+        read_status(&io, 5, false);
+        assert_eq!(io.open_pulses.get(), 0);
+        assert_eq!(io.sbs_pulses.get(), 0);
+    }
+}
@@ -0,0 +1,45 @@
+// Gate position, shared by GateServer and GateControl.
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GateState {
+    Open,
+    Closed,
+    // Moving or stopped between the limit sensors
+    Moving,
+    // Both limit sensors triggered at once: a sensor or its wiring has failed, position unknown
+    Fault,
+}
+
+impl GateState {
+    // Wire value of JSON field "s": 0 - open, 1 - closed, 2 - moving, 3 - sensor fault
+    pub const fn to_u8(self) -> u8 {
+        match self {
+            GateState::Open => 0,
+            GateState::Closed => 1,
+            GateState::Moving => 2,
+            GateState::Fault => 3,
+        }
+    }
+
+    pub const fn from_u8(value: u8) -> Option<GateState> {
+        match value {
+            0 => Some(GateState::Open),
+            1 => Some(GateState::Closed),
+            2 => Some(GateState::Moving),
+            3 => Some(GateState::Fault),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GateState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GateState::Open => "open",
+            GateState::Closed => "closed",
+            GateState::Moving => "moving",
+            GateState::Fault => "fault",
+        })
+    }
+}
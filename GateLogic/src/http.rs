@@ -0,0 +1,191 @@
+// HTTP request parsing without a server: URL and form codecs, query parameters, Basic
+// credentials, and the constant time compare of secrets. Used by GateServer handlers.
+
+// URL-decoded value of the query parameter from request URI. The settings page puts the token
+// in its URL encoded, so a token with + & % # / = ? matches only decoded
+pub fn query_param(uri: &str, name: &str) -> Option<String> {
+    let (_, query) = uri.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (url_decode(key) == name).then(|| url_decode(value))
+    })
+}
+
+// Decoded value of the field from application/x-www-form-urlencoded body
+pub fn form_field(body: &str, name: &str) -> Option<String> {
+    body.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (url_decode(key) == name).then(|| url_decode(value))
+    })
+}
+
+// Decode %XX escapes and '+' as space
+pub fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Percent-encode everything except unreserved characters, for URL query values
+pub fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Credentials user:pass of an Authorization: Basic header value, None - another scheme or
+// not valid base64
+pub fn basic_credentials(authorization: &str) -> Option<Vec<u8>> {
+    base64_decode(authorization.strip_prefix("Basic ")?.trim())
+}
+
+// Standard base64 with padding, None - not valid base64
+pub fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let encoded = encoded.as_bytes();
+    if encoded.len() % 4 != 0 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    for chunk in encoded.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut bits = 0u32;
+        for &c in &chunk[..4 - padding] {
+            bits = (bits << 6) | value(c)? as u32;
+        }
+        bits <<= 6 * padding;
+        let bytes = bits.to_be_bytes();
+        decoded.extend_from_slice(&bytes[1..4 - padding]);
+    }
+    Some(decoded)
+}
+
+// Compare without early exit on first mismatched byte, so the response time
+// does not tell how much of the token was guessed
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_time_eq_compares_whole_value() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret1"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+
+    #[test]
+    fn query_param_found_and_decoded() {
+        let uri = "/gate_open?rssi=-61&token=a%2Bb%26c%3D";
+        assert_eq!(query_param(uri, "token").as_deref(), Some("a+b&c="));
+        assert_eq!(query_param(uri, "rssi").as_deref(), Some("-61"));
+        assert_eq!(query_param("/x?t%6Fken=1", "token").as_deref(), Some("1"));
+        assert_eq!(query_param("/x?token=a+b", "token").as_deref(), Some("a b"));
+    }
+
+    #[test]
+    fn query_param_missing() {
+        assert_eq!(query_param("/gate_open", "token"), None);
+        assert_eq!(query_param("/gate_open?tokens=1", "token"), None);
+        assert_eq!(query_param("/gate_open?token", "token"), None);
+        assert_eq!(
+            query_param("/gate_open?token=", "token").as_deref(),
+            Some("")
+        );
+    }
+
+    #[test]
+    fn url_decode_keeps_bad_escapes() {
+        assert_eq!(url_decode("%D0%B2%D0%BE"), "во");
+        assert_eq!(url_decode("100%"), "100%");
+        assert_eq!(url_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn url_encode_round_trip() {
+        let value = "a b&c=d/€";
+        assert_eq!(url_encode("a-b_c.d~"), "a-b_c.d~");
+        assert_eq!(url_encode("a b&"), "a%20b%26");
+        assert_eq!(url_decode(&url_encode(value)), value);
+    }
+
+    #[test]
+    fn form_field_decoded() {
+        let body = "wifi_ssid=My+Home&wifi_psk=p%40ss";
+        assert_eq!(form_field(body, "wifi_ssid").as_deref(), Some("My Home"));
+        assert_eq!(form_field(body, "wifi_psk").as_deref(), Some("p@ss"));
+        assert_eq!(form_field(body, "gate_token"), None);
+    }
+
+    #[test]
+    fn base64_decode_with_padding() {
+        assert_eq!(
+            base64_decode("Z2F0ZTpwYXNz").as_deref(),
+            Some(&b"gate:pass"[..])
+        );
+        assert_eq!(base64_decode("YTpi").as_deref(), Some(&b"a:b"[..]));
+        assert_eq!(base64_decode("YTpiYw==").as_deref(), Some(&b"a:bc"[..]));
+        assert_eq!(base64_decode("YTpiYzE=").as_deref(), Some(&b"a:bc1"[..]));
+        assert_eq!(base64_decode("").as_deref(), Some(&b""[..]));
+    }
+
+    #[test]
+    fn base64_decode_rejects_invalid() {
+        assert_eq!(base64_decode("YTpi="), None);
+        assert_eq!(base64_decode("YT!i"), None);
+        assert_eq!(base64_decode("Y==="), None);
+    }
+
+    #[test]
+    fn basic_credentials_of_header() {
+        assert_eq!(
+            basic_credentials("Basic Z2F0ZTpwYXNz").as_deref(),
+            Some(&b"gate:pass"[..])
+        );
+        assert_eq!(basic_credentials("Bearer Z2F0ZTpwYXNz"), None);
+        assert_eq!(basic_credentials("Basic not base64"), None);
+    }
+}
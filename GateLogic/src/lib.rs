@@ -0,0 +1,10 @@
+// Gate logic free of hardware and ESP-IDF, shared by GateServer and GateControl.
+// Built for the host as well, so it is covered by unit tests.
+pub mod approach;
+pub mod auth;
+pub mod gate_io;
+pub mod gate_state;
+pub mod http;
+pub mod reply;
+pub mod status;
+pub mod urls;
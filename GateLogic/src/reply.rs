@@ -0,0 +1,77 @@
+// GateServer replies as read by GateControl: the gate status and the GateServer RSSI from
+// the JSON body. Only these fields are looked up, so the compact reply and the full status
+// JSON are read the same way.
+use crate::gate_state::GateState;
+
+// Gate status `s` from GateServer JSON reply like `{"s":2}`
+pub fn parse_gate_status(body: &str) -> Option<GateState> {
+    let (_, rest) = body.split_once("\"s\":")?;
+    let rest = rest.trim_start();
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    GateState::from_u8(rest[..end].parse().ok()?)
+}
+
+// `rssi` GateServer adds to command replies with command_rssi, like `{"s":2,"rssi":-61}`
+pub fn parse_server_rssi(body: &str) -> Option<i8> {
+    let (_, rest) = body.split_once("\"rssi\":")?;
+    let rest = rest.trim_start();
+    let end = rest
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+        .map_or(rest.len(), |(i, _)| i);
+    rest[..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // /gate_status reply as StatusReport writes it
+    const FULL_STATUS: &str = "{\"s\":1,\"moving_until\":null,\"opened\":false,\"closed\":true,\"rssi\":-58,\"ipv6\":[],\"uptime\":3600,\"version\":\"0.1.0\",\"error\":null,\"schedule\":\"\",\"mode\":\"normal\",\"remote\":true}";
+
+    #[test]
+    fn gate_status_of_compact_reply() {
+        assert_eq!(parse_gate_status("{\"s\":0}"), Some(GateState::Open));
+        assert_eq!(parse_gate_status("{\"s\":1}"), Some(GateState::Closed));
+        assert_eq!(parse_gate_status("{\"s\": 2}"), Some(GateState::Moving));
+        assert_eq!(parse_gate_status("{\"s\":3}"), Some(GateState::Fault));
+    }
+
+    #[test]
+    fn gate_status_with_rssi() {
+        let body = "{\"s\":2,\"rssi\":-61}";
+        assert_eq!(parse_gate_status(body), Some(GateState::Moving));
+        assert_eq!(parse_server_rssi(body), Some(-61));
+    }
+
+    #[test]
+    fn gate_status_of_full_status() {
+        assert_eq!(parse_gate_status(FULL_STATUS), Some(GateState::Closed));
+        assert_eq!(parse_server_rssi(FULL_STATUS), Some(-58));
+    }
+
+    #[test]
+    fn gate_status_out_of_range() {
+        assert_eq!(parse_gate_status("{\"s\":4}"), None);
+        assert_eq!(parse_gate_status("{\"s\":300}"), None);
+        assert_eq!(parse_gate_status("{\"s\":-1}"), None);
+    }
+
+    #[test]
+    fn gate_status_of_garbage() {
+        assert_eq!(parse_gate_status(""), None);
+        assert_eq!(parse_gate_status("OK"), None);
+        assert_eq!(parse_gate_status("{\"s\":\"open\"}"), None);
+        assert_eq!(parse_gate_status("{\"s\":"), None);
+        assert_eq!(parse_gate_status("{\"error\":\"unauthorized\"}"), None);
+    }
+
+    #[test]
+    fn server_rssi_missing_or_null() {
+        assert_eq!(parse_server_rssi("{\"s\":2}"), None);
+        assert_eq!(parse_server_rssi("{\"s\":2,\"rssi\":null}"), None);
+        assert_eq!(parse_server_rssi("{\"s\":2,\"rssi\":-300}"), None);
+    }
+}
@@ -0,0 +1,191 @@
+// Gate status reply of GateServer /gate_status, WebSocket and the status response_format, from
+// values GateServer collects. GateControl reads the s field of it, see reply.
+use std::net::Ipv6Addr;
+
+use crate::gate_state::GateState;
+
+// Gate status reply, formatted without touching the hardware
+pub struct StatusReport<'a> {
+    pub status: GateState,
+    // Milliseconds left of expected_travel_ms, None - not moving by a command
+    pub moving_until: Option<u64>,
+    // Raw sensor levels
+    pub opened: bool,
+    pub closed: bool,
+    // WiFi signal strength, None - not connected
+    pub rssi: Option<i8>,
+    // IPv6 addresses, empty without IPv6 (feature ipv6)
+    pub ipv6: &'a [Ipv6Addr],
+    // Seconds since start
+    pub uptime: u64,
+    // Firmware version
+    pub version: &'a str,
+    // "sensors" if both limit sensors are triggered, "stalled" if the gate stopped between the
+    // limits after expected_travel_ms, "timeout" if the gate did not reach a limit after the last
+    // command, None - no error
+    pub error: Option<&'a str>,
+    // Schedule JSON, see schedule::json()
+    pub schedule: &'a str,
+    // Operating mode: normal, hold_open or locked
+    pub mode: &'a str,
+    // GateControl presence JSON, see remote::json()
+    pub remote: &'a str,
+}
+
+// Status and error reported for the gate status: a stalled gate is reported as a fault.
+// Sensor fault outweighs a stall and a travel timeout, all need a visit to the gate
+pub fn reported(
+    status: GateState,
+    stalled: bool,
+    travel_error: Option<&'static str>,
+) -> (GateState, Option<&'static str>) {
+    if status == GateState::Fault {
+        (status, Some("sensors"))
+    } else if stalled {
+        (GateState::Fault, Some("stalled"))
+    } else {
+        (status, travel_error)
+    }
+}
+
+impl StatusReport<'_> {
+    // s - gate status (GateState wire value), null for absent moving_until, rssi and error
+    pub fn json(&self) -> String {
+        let moving_until = match self.moving_until {
+            Some(ms) => ms.to_string(),
+            None => "null".to_string(),
+        };
+        let rssi = match self.rssi {
+            Some(rssi) => rssi.to_string(),
+            None => "null".to_string(),
+        };
+        let ipv6 = self
+            .ipv6
+            .iter()
+            .map(|addr| format!("\"{}\"", addr))
+            .collect::<Vec<_>>()
+            .join(",");
+        let error = match self.error {
+            Some(error) => format!("\"{}\"", error),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"s\":{},\"moving_until\":{},\"opened\":{},\"closed\":{},\"rssi\":{},\"ipv6\":[{}],\"uptime\":{},\"version\":\"{}\",\"error\":{},\"schedule\":{},\"mode\":\"{}\",\"remote\":{}}}",
+            self.status.to_u8(),
+            moving_until,
+            self.opened,
+            self.closed,
+            rssi,
+            ipv6,
+            self.uptime,
+            self.version,
+            error,
+            self.schedule,
+            self.mode,
+            self.remote
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(ipv6: &[Ipv6Addr]) -> StatusReport<'_> {
+        StatusReport {
+            status: GateState::Closed,
+            moving_until: None,
+            opened: false,
+            closed: true,
+            rssi: None,
+            ipv6,
+            uptime: 3600,
+            version: "0.1.0",
+            error: None,
+            schedule: "null",
+            mode: "normal",
+            remote: "null",
+        }
+    }
+
+    #[test]
+    fn sensor_fault_outweighs_stall_and_timeout() {
+        assert_eq!(
+            reported(GateState::Fault, true, Some("timeout")),
+            (GateState::Fault, Some("sensors"))
+        );
+        assert_eq!(
+            reported(GateState::Moving, true, Some("timeout")),
+            (GateState::Fault, Some("stalled"))
+        );
+        assert_eq!(
+            reported(GateState::Open, false, Some("timeout")),
+            (GateState::Open, Some("timeout"))
+        );
+        assert_eq!(
+            reported(GateState::Closed, false, None),
+            (GateState::Closed, None)
+        );
+    }
+
+    #[test]
+    fn json_of_idle_gate() {
+        assert_eq!(
+            report(&[]).json(),
+            "{\"s\":1,\"moving_until\":null,\"opened\":false,\"closed\":true,\"rssi\":null,\"ipv6\":[],\"uptime\":3600,\"version\":\"0.1.0\",\"error\":null,\"schedule\":null,\"mode\":\"normal\",\"remote\":null}"
+        );
+    }
+
+    #[test]
+    fn s_is_wire_value() {
+        for status in [
+            GateState::Open,
+            GateState::Closed,
+            GateState::Moving,
+            GateState::Fault,
+        ] {
+            let json = StatusReport {
+                status,
+                ..report(&[])
+            }
+            .json();
+            assert!(json.starts_with(&format!("{{\"s\":{},", status.to_u8())));
+            assert_eq!(crate::reply::parse_gate_status(&json), Some(status));
+        }
+    }
+
+    #[test]
+    fn present_values_replace_null() {
+        let json = StatusReport {
+            moving_until: Some(12000),
+            rssi: Some(-61),
+            error: Some("timeout"),
+            ..report(&[])
+        }
+        .json();
+        assert!(json.contains("\"moving_until\":12000,"));
+        assert!(json.contains("\"rssi\":-61,"));
+        assert!(json.contains("\"error\":\"timeout\","));
+        assert!(!json.contains("null,\"opened\""));
+    }
+
+    #[test]
+    fn ipv6_addresses_listed_as_strings() {
+        let one = ["fe80::1".parse().unwrap()];
+        assert!(report(&one).json().contains("\"ipv6\":[\"fe80::1\"],"));
+        let two = ["fe80::1".parse().unwrap(), "2001:db8::5".parse().unwrap()];
+        assert!(report(&two)
+            .json()
+            .contains("\"ipv6\":[\"fe80::1\",\"2001:db8::5\"],"));
+    }
+
+    #[test]
+    fn nested_json_kept_as_is() {
+        let json = StatusReport {
+            remote: "{\"seen_secs_ago\":12,\"rssi\":-67}",
+            ..report(&[])
+        }
+        .json();
+        assert!(json.ends_with(",\"remote\":{\"seen_secs_ago\":12,\"rssi\":-67}}"));
+    }
+}
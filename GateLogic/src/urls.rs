@@ -0,0 +1,116 @@
+// GateServer URLs of GateControl as configured: gate_host or gate_*_url, checked and corrected
+// for common typos. Logging and the config are left to GateControl.
+
+// http:// base URL with port, unless it is the default port or the host has a port already.
+// HTTPS server always listens on 443, so https:// URLs are kept as is
+pub fn with_port(base: &str, port: u16) -> String {
+    match base.strip_prefix("http://") {
+        Some(host) if port != 80 && !host.contains(':') => format!("http://{}:{}", host, port),
+        _ => base.to_string(),
+    }
+}
+
+// Heartbeat URL next to the status URL: the status URL without its last path segment plus /ping,
+// a bare host is kept whole
+pub fn ping_url(status_url: &str) -> String {
+    let base = status_url
+        .rsplit_once('/')
+        .filter(|(base, _)| !base.ends_with('/'))
+        .map_or(status_url, |(base, _)| base);
+    format!("{}/ping", base)
+}
+
+// Fix a mistyped scheme separator like http/host or http:/host, add http:// to
+// a bare host, then check that the host is present
+pub fn normalize(url: &str) -> Result<String, &'static str> {
+    let url = url.trim();
+    let (scheme, rest) = match url.split_once(':') {
+        Some((scheme, rest)) if scheme == "http" || scheme == "https" => {
+            (scheme, rest.trim_start_matches('/'))
+        }
+        _ => match url.split_once('/') {
+            Some((scheme, rest)) if scheme == "http" || scheme == "https" => {
+                (scheme, rest.trim_start_matches('/'))
+            }
+            _ => ("http", url),
+        },
+    };
+    if rest.contains("://") {
+        return Err("scheme is not http or https");
+    }
+    let host = rest.split('/').next().unwrap_or("");
+    if host.is_empty() {
+        return Err("no host");
+    }
+    if rest.contains(char::is_whitespace) {
+        return Err("contains spaces");
+    }
+    Ok(format!("{}://{}", scheme, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_keeps_good_urls() {
+        assert_eq!(
+            normalize("http://gate.local/gate_open").as_deref(),
+            Ok("http://gate.local/gate_open")
+        );
+        assert_eq!(
+            normalize("https://192.168.0.1/gate_sbs").as_deref(),
+            Ok("https://192.168.0.1/gate_sbs")
+        );
+    }
+
+    #[test]
+    fn normalize_fixes_typos() {
+        assert_eq!(normalize("gate.local").as_deref(), Ok("http://gate.local"));
+        assert_eq!(
+            normalize("http/gate.local/gate_open").as_deref(),
+            Ok("http://gate.local/gate_open")
+        );
+        assert_eq!(
+            normalize("http:/gate.local").as_deref(),
+            Ok("http://gate.local")
+        );
+        assert_eq!(
+            normalize(" http://gate.local ").as_deref(),
+            Ok("http://gate.local")
+        );
+    }
+
+    #[test]
+    fn normalize_rejects_malformed() {
+        assert_eq!(
+            normalize("ftp://gate.local"),
+            Err("scheme is not http or https")
+        );
+        assert_eq!(normalize("http://"), Err("no host"));
+        assert_eq!(normalize("http://gate local/"), Err("contains spaces"));
+    }
+
+    #[test]
+    fn with_port_only_for_plain_http() {
+        assert_eq!(
+            with_port("http://gate.local", 8080),
+            "http://gate.local:8080"
+        );
+        assert_eq!(with_port("http://gate.local", 80), "http://gate.local");
+        assert_eq!(
+            with_port("http://gate.local:81", 8080),
+            "http://gate.local:81"
+        );
+        assert_eq!(with_port("https://gate.local", 8080), "https://gate.local");
+    }
+
+    #[test]
+    fn ping_next_to_status() {
+        assert_eq!(
+            ping_url("http://gate.local/gate_status"),
+            "http://gate.local/ping"
+        );
+        assert_eq!(ping_url("http://gate.local"), "http://gate.local/ping");
+    }
+}
@@ -0,0 +1,205 @@
+use embedded_svc::http::server::Request;
+use esp_idf_svc::http::server::EspHttpConnection;
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::{
+    net::{IpAddr, Ipv6Addr},
+    sync::Arc,
+};
+
+use crate::storage::{self, Namespace, Nvs};
+use crate::web::peer_ip;
+use crate::START_TIME;
+
+// Ring buffer size, each event has its own NVS slot ev0..ev49 overwritten in turn,
+// so flash usage does not grow and every write touches one small entry only
+const LOG_SIZE: u32 = 50;
+const EVENT_LEN: usize = 31;
+
+// Logged gate command
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Open,
+    Sbs,
+    Close,
+    Macro,
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Open => "open",
+            Action::Sbs => "sbs",
+            Action::Close => "close",
+            Action::Macro => "macro",
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    fn from_u8(value: u8) -> Option<Action> {
+        match value {
+            0 => Some(Action::Open),
+            1 => Some(Action::Sbs),
+            2 => Some(Action::Close),
+            3 => Some(Action::Macro),
+            _ => None,
+        }
+    }
+}
+
+// Log entry. There is no wall clock, so time is boot number and seconds since that boot
+struct Event {
+    seq: u32,
+    boot: u32,
+    uptime: u32,
+    action: Action,
+    // HTTP status of the response: 200 - executed, 401 - bad token, 403 - locked, 429 - too fast
+    status: u16,
+    // Unspecified address if the client address is unknown
+    ip: Ipv6Addr,
+}
+
+impl Event {
+    fn to_bytes(&self) -> [u8; EVENT_LEN] {
+        let mut buf = [0u8; EVENT_LEN];
+        buf[0..4].copy_from_slice(&self.seq.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.boot.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.uptime.to_le_bytes());
+        buf[12] = self.action.to_u8();
+        buf[13..15].copy_from_slice(&self.status.to_le_bytes());
+        buf[15..31].copy_from_slice(&self.ip.octets());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Option<Event> {
+        if buf.len() != EVENT_LEN {
+            return None;
+        }
+        let mut ip = [0u8; 16];
+        ip.copy_from_slice(&buf[15..31]);
+        Some(Event {
+            seq: u32::from_le_bytes(buf[0..4].try_into().ok()?),
+            boot: u32::from_le_bytes(buf[4..8].try_into().ok()?),
+            uptime: u32::from_le_bytes(buf[8..12].try_into().ok()?),
+            action: Action::from_u8(buf[12])?,
+            status: u16::from_le_bytes(buf[13..15].try_into().ok()?),
+            ip: Ipv6Addr::from(ip),
+        })
+    }
+
+    fn to_json(&self) -> String {
+        let ip = match self.ip.to_ipv4_mapped() {
+            Some(ip) => format!("\"{}\"", ip),
+            None if self.ip.is_unspecified() => "null".to_string(),
+            None => format!("\"{}\"", self.ip),
+        };
+        format!(
+            "{{\"seq\":{},\"boot\":{},\"uptime\":{},\"action\":\"{}\",\"ip\":{},\"status\":{}}}",
+            self.seq,
+            self.boot,
+            self.uptime,
+            self.action.as_str(),
+            ip,
+            self.status
+        )
+    }
+}
+
+struct AccessLog {
+    nvs: Nvs,
+    // Sequence number of the next event, its slot is next % LOG_SIZE
+    next: u32,
+    boot: u32,
+}
+
+lazy_static! {
+    /// Access log in NVS, None if NVS can not be opened
+    static ref ACCESS_LOG: Arc<Mutex<Option<AccessLog>>> = Arc::new(Mutex::new(open()));
+}
+
+// Open the log at startup, so the boot is counted even if no command arrives
+pub fn init() {
+    lazy_static::initialize(&ACCESS_LOG);
+}
+
+// Open the log and count this boot
+fn open_log() -> anyhow::Result<AccessLog> {
+    let mut nvs = storage::open(Namespace::Log)?;
+    let next = nvs.get_u32("next").unwrap_or(0);
+    let boot = nvs.get_u32("boot").unwrap_or(0).wrapping_add(1);
+    nvs.set_u32("boot", boot)?;
+    Ok(AccessLog { nvs, next, boot })
+}
+
+fn open() -> Option<AccessLog> {
+    match open_log() {
+        Ok(log) => {
+            info!(
+                "Access log opened, boot {}, {} events logged",
+                log.boot, log.next
+            );
+            Some(log)
+        }
+        Err(e) => {
+            warn!(
+                "Can not open access log in NVS, events are not logged: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+// Record a gate command request with the response status
+pub fn record(request: &mut Request<&mut EspHttpConnection>, action: Action, status: u16) {
+    let ip = match peer_ip(request) {
+        Some(IpAddr::V4(ip)) => ip.to_ipv6_mapped(),
+        Some(IpAddr::V6(ip)) => ip,
+        None => Ipv6Addr::UNSPECIFIED,
+    };
+    let access_log = ACCESS_LOG.clone();
+    let mut access_log = access_log.lock();
+    let Some(log) = access_log.as_mut() else {
+        return;
+    };
+    let event = Event {
+        seq: log.next,
+        boot: log.boot,
+        uptime: START_TIME.elapsed().as_secs() as u32,
+        action,
+        status,
+        ip,
+    };
+    let key = format!("ev{}", event.seq % LOG_SIZE);
+    let result = log
+        .nvs
+        .set_raw(&key, &event.to_bytes())
+        .and_then(|_| log.nvs.set_u32("next", event.seq.wrapping_add(1)));
+    match result {
+        Ok(()) => log.next = event.seq.wrapping_add(1),
+        Err(e) => error!("Can not write access log event to NVS: {}", e),
+    }
+}
+
+// Logged events as JSON array, oldest first
+pub fn json() -> String {
+    let access_log = ACCESS_LOG.clone();
+    let access_log = access_log.lock();
+    let Some(log) = access_log.as_ref() else {
+        return "[]".to_string();
+    };
+    let first = log.next.saturating_sub(LOG_SIZE);
+    let mut buf = [0u8; EVENT_LEN];
+    let events: Vec<String> = (first..log.next)
+        .filter_map(|seq| {
+            let key = format!("ev{}", seq % LOG_SIZE);
+            log.nvs.get_raw(&key, &mut buf).and_then(Event::from_bytes)
+        })
+        .map(|event| event.to_json())
+        .collect();
+    format!("[{}]", events.join(","))
+}
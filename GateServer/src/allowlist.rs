@@ -0,0 +1,81 @@
+// Source address allowlist for gate commands, checked in addition to the token.
+// allowed_ips is a comma separated list of addresses and networks like
+// 192.168.0.10, 192.168.0.64/28, fd00::/8. Empty - commands are accepted from any address.
+// Malformed entries are logged at startup and dropped, so they never widen the list.
+use embedded_svc::http::server::Request;
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use lazy_static::lazy_static;
+use log::error;
+use std::net::IpAddr;
+
+use crate::{config, web::json_error};
+
+lazy_static! {
+    // Networks as address and prefix length
+    static ref ALLOWED: Vec<(IpAddr, u8)> = parse(config().allowed_ips);
+}
+
+fn parse(list: &str) -> Vec<(IpAddr, u8)> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let network = parse_network(entry);
+            if network.is_none() {
+                error!("Malformed allowed_ips entry {:?} ignored", entry);
+            }
+            network
+        })
+        .collect()
+}
+
+// "ip" or "ip/prefix", the prefix no longer than the address
+fn parse_network(entry: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = match entry.split_once('/') {
+        Some((ip, prefix)) => (ip.parse::<IpAddr>().ok()?, Some(prefix.parse::<u8>().ok()?)),
+        None => (entry.parse::<IpAddr>().ok()?, None),
+    };
+    let bits = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(bits);
+    (prefix <= bits).then_some((ip, prefix))
+}
+
+// Report malformed entries at startup rather than on the first command
+pub fn init() {
+    lazy_static::initialize(&ALLOWED);
+}
+
+pub fn enabled() -> bool {
+    !config().allowed_ips.trim().is_empty()
+}
+
+// Commands from ip are accepted. An unknown address is allowed only without a list
+pub fn allowed(ip: Option<IpAddr>) -> bool {
+    if !enabled() {
+        return true;
+    }
+    ip.is_some_and(|ip| {
+        ALLOWED
+            .iter()
+            .any(|&(network, prefix)| contains(network, prefix, ip))
+    })
+}
+
+fn contains(network: IpAddr, prefix: u8, ip: IpAddr) -> bool {
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+// 403 response for commands from an address not in allowed_ips
+pub fn forbidden(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    json_error(request, 403, "address_not_allowed", None)
+}
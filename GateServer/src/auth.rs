@@ -0,0 +1,72 @@
+use embedded_svc::http::server::Request;
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use gate_logic::auth::Credentials;
+use log::{info, warn};
+
+pub use gate_logic::http::query_param;
+
+use crate::web::json_error_with_headers;
+use crate::{config, settings};
+
+// Browsers prompt for credentials on a 401 with this challenge
+const BASIC_CHALLENGE: (&str, &str) = (
+    "WWW-Authenticate",
+    "Basic realm=\"Gate\", charset=\"UTF-8\"",
+);
+
+// Either credential is checked once configured: gate_token in settings or basic_user in config
+pub fn auth_required() -> bool {
+    credentials(&settings::current().gate_token).required()
+}
+
+// Access decision of gate_logic::auth on the X-Gate-Token and Authorization headers and URI
+pub fn is_authorized(request: &Request<&mut EspHttpConnection>) -> bool {
+    let gate_token = settings::current().gate_token;
+    credentials(&gate_token).accept(
+        request.header("X-Gate-Token"),
+        request.uri(),
+        request.header("Authorization"),
+    )
+}
+
+// Handler of an authenticated URI: the call is logged as name, and the request is answered 401
+// unless is_authorized()
+pub fn authorized<F>(
+    name: &'static str,
+    handler: F,
+) -> impl for<'r> Fn(Request<&mut EspHttpConnection<'r>>) -> Result<(), EspIOError> + Send + 'static
+where
+    F: for<'r> Fn(Request<&mut EspHttpConnection<'r>>) -> Result<(), EspIOError> + Send + 'static,
+{
+    move |request: Request<&mut EspHttpConnection>| {
+        info!("{} called", name);
+        if !is_authorized(&request) {
+            warn!("{} rejected: wrong or missing token", name);
+            return unauthorized(request);
+        }
+        handler(request)
+    }
+}
+
+// 401 response for rejected command requests, with a Basic challenge if Basic Auth is configured
+pub fn unauthorized(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let headers: &[(&str, &str)] = if basic_enabled() {
+        &[BASIC_CHALLENGE]
+    } else {
+        &[]
+    };
+    json_error_with_headers(request, 401, "unauthorized", None, headers)
+}
+
+fn basic_enabled() -> bool {
+    !config().basic_user.is_empty()
+}
+
+// gate_token of the settings with basic_user and basic_pass of the config
+fn credentials(gate_token: &str) -> Credentials<'_> {
+    Credentials {
+        gate_token,
+        basic_user: config().basic_user,
+        basic_pass: config().basic_pass,
+    }
+}
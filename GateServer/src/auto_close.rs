@@ -0,0 +1,90 @@
+use esp_idf_hal::delay::FreeRtos;
+use lazy_static::lazy_static;
+use log::info;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::mode::{self, Mode};
+use crate::{gate_state::GateState, gate_status, pulse_sbs, settings};
+
+struct PendingClose {
+    deadline: Instant,
+    // Gate has left the closed position since the timer was armed
+    left_closed: bool,
+}
+
+lazy_static! {
+    /// Pending auto-close, shared by HTTP handlers and the timer task
+    static ref PENDING_CLOSE: Arc<Mutex<Option<PendingClose>>> = Arc::new(Mutex::new(None));
+}
+
+// Start auto-close countdown after an open command
+pub fn arm() {
+    let auto_close_secs = settings::current().auto_close_secs;
+    if auto_close_secs == 0 {
+        return;
+    }
+    if mode::current() == Mode::HoldOpen {
+        info!("Hold open mode, auto-close is not armed");
+        return;
+    }
+    info!(
+        "Gate will be closed automatically in {} seconds",
+        auto_close_secs
+    );
+    let pending = PENDING_CLOSE.clone();
+    *pending.lock() = Some(PendingClose {
+        deadline: Instant::now() + Duration::from_secs(auto_close_secs as u64),
+        left_closed: false,
+    });
+}
+
+// Drop pending auto-close, if any
+pub fn cancel() {
+    let pending = PENDING_CLOSE.clone();
+    if pending.lock().take().is_some() {
+        info!("Auto-close cancelled");
+    }
+}
+
+// Timer task, lives outside the WiFi reconnect loop
+pub fn spawn_task() -> anyhow::Result<()> {
+    std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(|| loop {
+            FreeRtos::delay_ms(1000);
+            check();
+        })?;
+    Ok(())
+}
+
+fn check() {
+    let pending = PENDING_CLOSE.clone();
+    let mut pending = pending.lock();
+    let Some(close) = pending.as_mut() else {
+        return;
+    };
+    let status = gate_status();
+    if status != GateState::Closed {
+        close.left_closed = true;
+    } else if close.left_closed {
+        info!("Gate closed before auto-close timer elapsed");
+        *pending = None;
+        return;
+    }
+    if Instant::now() < close.deadline {
+        return;
+    }
+    // Take the pending close before pulsing, so it can not fire twice
+    *pending = None;
+    drop(pending);
+    if status == GateState::Open {
+        info!("Auto-close timer elapsed, closing gate");
+        pulse_sbs();
+    } else {
+        info!("Auto-close timer elapsed, but gate is not opened");
+    }
+}
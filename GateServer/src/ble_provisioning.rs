@@ -0,0 +1,82 @@
+// BLE provisioning of WiFi credentials by the ESP-IDF provisioning manager (wifi_prov_mgr),
+// built with feature "ble", needs the Bluetooth options of sdkconfig.defaults.ble added to the
+// build (see README).
+// Started at boot when no WiFi SSID is set, neither in cfg.toml nor in NVS. A phone app
+// (ESP BLE Provisioning by Espressif) finds the server as ble_service_name, proves possession
+// with ble_pop and sends the credentials. The manager checks them by connecting, then they are
+// stored to NVS like POST /config does, and the usual WiFi connection proceeds.
+use core::ffi::c_void;
+use esp_idf_hal::peripheral::Peripheral;
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    sys::{self, esp},
+    wifi::EspWifi,
+};
+use log::info;
+use std::ffi::CString;
+
+use crate::{config, hardware, settings};
+
+// Provisioning is needed: no SSID to connect to
+pub fn needed() -> bool {
+    settings::wifi_credentials().0.is_empty()
+}
+
+// Wait for credentials from the phone app and store them. Blocks until provisioning succeeds
+pub fn run() -> anyhow::Result<()> {
+    let service_name = CString::new(config().ble_service_name)?;
+    let pop = CString::new(config().ble_pop)?;
+    // Manager needs the WiFi driver and its netifs, they are dropped before connect_wifi().
+    // Without NVS the driver keeps the received config in RAM, it is read back below
+    let modem = unsafe {
+        hardware()
+            .peripherals
+            .clone()
+            .lock()
+            .modem
+            .clone_unchecked()
+    };
+    let _wifi = EspWifi::new(modem, EspSystemEventLoop::take()?, None)?;
+    let manager_config = sys::wifi_prov_mgr_config_t {
+        scheme: unsafe { sys::wifi_prov_scheme_ble },
+        // Bluetooth memory is released once provisioning ends, WiFi needs it
+        scheme_event_handler: sys::wifi_prov_event_handler_t {
+            event_cb: Some(sys::wifi_prov_scheme_ble_event_cb_free_btdm),
+            user_data: core::ptr::null_mut(),
+        },
+        app_event_handler: sys::wifi_prov_event_handler_t {
+            event_cb: None,
+            user_data: core::ptr::null_mut(),
+        },
+    };
+    esp!(unsafe { sys::wifi_prov_mgr_init(manager_config) })?;
+    info!(
+        "No WiFi credentials. BLE provisioning started as {}",
+        config().ble_service_name
+    );
+    let started = esp!(unsafe {
+        sys::wifi_prov_mgr_start_provisioning(
+            sys::wifi_prov_security_WIFI_PROV_SECURITY_1,
+            pop.as_ptr() as *const c_void,
+            service_name.as_ptr(),
+            core::ptr::null(),
+        )
+    });
+    if started.is_ok() {
+        unsafe { sys::wifi_prov_mgr_wait() };
+    }
+    unsafe { sys::wifi_prov_mgr_deinit() };
+    started?;
+    let mut wifi_config: sys::wifi_config_t = unsafe { core::mem::zeroed() };
+    esp!(unsafe { sys::esp_wifi_get_config(sys::wifi_interface_t_WIFI_IF_STA, &mut wifi_config) })?;
+    let sta = unsafe { wifi_config.sta };
+    let wifi_ssid = nul_terminated(&sta.ssid);
+    info!("BLE provisioning received credentials of {}", wifi_ssid);
+    settings::save_wifi(&wifi_ssid, &nul_terminated(&sta.password))
+}
+
+// Text of a fixed size C string field, up to the first NUL
+fn nul_terminated(field: &[u8]) -> String {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
@@ -0,0 +1,162 @@
+// GateServer pin assignments (ESP32-C3). Porting to another board or ESP32 variant
+// needs changes in this module only: a pin type alias and the matching peripheral field.
+//
+// Outputs, relay coils driven active high (contact closed while high), active low with
+// relay_active_low:
+//   GPIO3  - "open" input of RTO-1000
+//   GPIO10 - step-by-step (SBS) input of RTO-1000
+// Inputs, internal pull-up enabled:
+//   GPIO0  - gate opened limit sensor, active high (active low with sensors_active_low)
+//   GPIO1  - gate closed limit sensor, active high (active low with sensors_active_low)
+//   GPIO4  - local SBS button to GND, active low (button_enabled)
+// Second gate pins are not fixed, they are listed in gate2_pins config.
+// Optional status LED on status_led_pin config, a WS2812 is driven by RMT channel 0.
+// Optional warning buzzer on buzzer_pin config, active high.
+use esp_idf_hal::{gpio::*, peripheral::Peripheral, peripherals::Peripherals, rmt::CHANNEL0};
+
+// GPIOs of the ESP32-C3 which may be used for the second gate: not taken by the main gate
+// or the button, not strapping (GPIO2, GPIO8, GPIO9), SPI flash (GPIO12..17) or USB (GPIO18, GPIO19)
+const GATE2_GPIOS: [i32; 5] = [5, 6, 7, 20, 21];
+// Status LED GPIOs: the same free ones and GPIO8, the on-board WS2812 of the DevKits.
+// GPIO8 is strapping, but only sampled at reset, so driving it afterwards is fine
+const STATUS_LED_GPIOS: [i32; 6] = [5, 6, 7, 8, 20, 21];
+
+pub type GateOpenPin = Gpio3;
+pub type GateSbsPin = Gpio10;
+// GPIOs of GateOpenPin and GateSbsPin, released by number before any driver is set up
+const RELAY_GPIOS: [i32; 2] = [3, 10];
+pub type GateOpenedPin = Gpio0;
+pub type GateClosedPin = Gpio1;
+pub type ButtonPin = Gpio4;
+pub type StatusLedChannel = CHANNEL0;
+
+// Pins are taken unchecked, so each one has to be taken only once
+
+pub fn gate_open_pin(peripherals: &mut Peripherals) -> GateOpenPin {
+    unsafe { peripherals.pins.gpio3.clone_unchecked() }
+}
+
+pub fn gate_sbs_pin(peripherals: &mut Peripherals) -> GateSbsPin {
+    unsafe { peripherals.pins.gpio10.clone_unchecked() }
+}
+
+pub fn gate_opened_pin(peripherals: &mut Peripherals) -> GateOpenedPin {
+    unsafe { peripherals.pins.gpio0.clone_unchecked() }
+}
+
+pub fn gate_closed_pin(peripherals: &mut Peripherals) -> GateClosedPin {
+    unsafe { peripherals.pins.gpio1.clone_unchecked() }
+}
+
+pub fn button_pin(peripherals: &mut Peripherals) -> ButtonPin {
+    unsafe { peripherals.pins.gpio4.clone_unchecked() }
+}
+
+pub fn status_led_channel(peripherals: &mut Peripherals) -> StatusLedChannel {
+    unsafe { peripherals.rmt.channel0.clone_unchecked() }
+}
+
+// Open relay, SBS relay, opened sensor and closed sensor pins of the second gate
+pub type Gate2Pins = (AnyOutputPin, AnyOutputPin, AnyIOPin, AnyIOPin);
+
+// Second gate pins from comma separated GPIO numbers "open,sbs,opened,closed".
+// None - empty list, single gate. Each GPIO has to be one of GATE2_GPIOS and used once
+pub fn gate2_pins(gpios: &str) -> anyhow::Result<Option<Gate2Pins>> {
+    if gpios.trim().is_empty() {
+        return Ok(None);
+    }
+    let gpios = gpios
+        .split(',')
+        .map(|gpio| gpio.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow::anyhow!("gate2_pins {:?} are not GPIO numbers", gpios))?;
+    let [open, sbs, opened, closed] = gpios[..] else {
+        anyhow::bail!("gate2_pins needs 4 GPIOs: open, sbs, opened, closed");
+    };
+    for (i, gpio) in gpios.iter().enumerate() {
+        if !GATE2_GPIOS.contains(gpio) {
+            anyhow::bail!(
+                "GPIO{} can not be used, free GPIOs are {:?}",
+                gpio,
+                GATE2_GPIOS
+            );
+        }
+        if gpios[..i].contains(gpio) {
+            anyhow::bail!("GPIO{} is listed twice", gpio);
+        }
+    }
+    // Checked above to be free GPIOs, each taken once
+    unsafe {
+        Ok(Some((
+            AnyOutputPin::new(open),
+            AnyOutputPin::new(sbs),
+            AnyIOPin::new(opened),
+            AnyIOPin::new(closed),
+        )))
+    }
+}
+
+// GPIO numbers of all relay outputs: the main gate ones and those of gate2_pins, if valid
+pub fn relay_gpios(gate2_pins: &str) -> Vec<i32> {
+    let mut gpios = RELAY_GPIOS.to_vec();
+    if let Ok(Some((open, sbs, _, _))) = self::gate2_pins(gate2_pins) {
+        gpios.extend([open.pin(), sbs.pin()]);
+    }
+    gpios
+}
+
+// Status LED pin from its GPIO number, None - empty, no LED.
+// The GPIO has to be one of STATUS_LED_GPIOS and not listed in gate2_pins
+pub fn status_led_pin(gpio: &str, gate2_pins: &str) -> anyhow::Result<Option<AnyOutputPin>> {
+    single_pin(
+        "status_led_pin",
+        gpio,
+        &STATUS_LED_GPIOS,
+        &[("second gate", gate2_pins)],
+    )
+}
+
+// Buzzer pin from its GPIO number, None - empty, no buzzer.
+// The GPIO has to be one of GATE2_GPIOS and not taken by the second gate or the status LED
+pub fn buzzer_pin(
+    gpio: &str,
+    gate2_pins: &str,
+    status_led_pin: &str,
+) -> anyhow::Result<Option<AnyOutputPin>> {
+    single_pin(
+        "buzzer_pin",
+        gpio,
+        &GATE2_GPIOS,
+        &[("second gate", gate2_pins), ("status LED", status_led_pin)],
+    )
+}
+
+// Output pin from the GPIO number of config name, None - empty. The GPIO has to be one
+// of free and not in the comma separated GPIO lists of taken, which are named for the error
+fn single_pin(
+    name: &str,
+    gpio: &str,
+    free: &[i32],
+    taken: &[(&str, &str)],
+) -> anyhow::Result<Option<AnyOutputPin>> {
+    if gpio.trim().is_empty() {
+        return Ok(None);
+    }
+    let gpio = gpio
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| anyhow::anyhow!("{} {:?} is not a GPIO number", name, gpio))?;
+    if !free.contains(&gpio) {
+        anyhow::bail!("GPIO{} can not be used, free GPIOs are {:?}", gpio, free);
+    }
+    for (user, gpios) in taken {
+        if gpios
+            .split(',')
+            .any(|taken_gpio| taken_gpio.trim().parse() == Ok(gpio))
+        {
+            anyhow::bail!("GPIO{} is used by the {}", gpio, user);
+        }
+    }
+    // Checked above to be a free GPIO, taken once
+    unsafe { Ok(Some(AnyOutputPin::new(gpio))) }
+}
@@ -0,0 +1,33 @@
+use esp_idf_hal::{
+    delay::FreeRtos,
+    gpio::{PinDriver, Pull},
+};
+use log::info;
+
+use crate::{board, gate_sbs, hardware};
+
+// Local SBS button task (active low, internal pull-up), pin in board module.
+// Runs outside the WiFi reconnect loop, so the button works while WiFi is down.
+pub fn spawn_task() -> anyhow::Result<()> {
+    let peripherals = hardware().peripherals.clone();
+    let mut peripherals = peripherals.lock();
+    let mut button = PinDriver::input(board::button_pin(&mut peripherals))?;
+    drop(peripherals);
+    button.set_pull(Pull::Up)?;
+    std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(move || loop {
+            if button.is_low() {
+                info!("Gate SBS button pressed");
+                gate_sbs();
+                // Avoid contact bounce and duplicate sensing
+                FreeRtos::delay_ms(100);
+                while button.is_low() {
+                    FreeRtos::delay_ms(100);
+                }
+            } else {
+                FreeRtos::delay_ms(100);
+            }
+        })?;
+    Ok(())
+}
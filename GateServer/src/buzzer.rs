@@ -0,0 +1,80 @@
+// Optional warning buzzer on buzzer_pin for installations which require an audible warning
+// before a motorized gate moves. Before an open or SBS relay pulse the buzzer sounds for
+// warning_beep_ms and the pulse waits warning_delay_ms from the beep start. The beep is ended
+// by its own task, so a beep longer than the delay goes on while the gate starts.
+use esp_idf_hal::{
+    delay::FreeRtos,
+    gpio::{AnyOutputPin, Output, PinDriver},
+};
+use lazy_static::lazy_static;
+use log::{error, info};
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::config;
+
+// Beep end is checked this often
+const TICK_MS: u32 = 20;
+
+lazy_static! {
+    // Buzzer output, None - not configured or failed to set up
+    static ref BUZZER: Arc<Mutex<Option<PinDriver<'static, AnyOutputPin, Output>>>> =
+        Arc::new(Mutex::new(None));
+    // Buzzer sounds until then
+    static ref BEEP_UNTIL: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
+pub fn init(pin: AnyOutputPin) -> anyhow::Result<()> {
+    let mut buzzer = PinDriver::output(pin)?;
+    buzzer.set_low()?;
+    *BUZZER.clone().lock() = Some(buzzer);
+    info!("Warning buzzer on GPIO{}", config().buzzer_pin);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    BUZZER.clone().lock().is_some()
+}
+
+// Start the beep and wait warning_delay_ms, called right before a relay pulse moves the gate.
+// Returns at once without a buzzer
+pub fn warn_before_motion() {
+    if !enabled() {
+        return;
+    }
+    let beep = Duration::from_millis(config().warning_beep_ms as u64);
+    *BEEP_UNTIL.clone().lock() = Some(Instant::now() + beep);
+    set(true);
+    FreeRtos::delay_ms(config().warning_delay_ms);
+}
+
+fn set(on: bool) {
+    if let Some(buzzer) = BUZZER.clone().lock().as_mut() {
+        let result = if on {
+            buzzer.set_high()
+        } else {
+            buzzer.set_low()
+        };
+        if let Err(e) = result {
+            error!("Can not switch buzzer: {}", e);
+        }
+    }
+}
+
+pub fn spawn_task() -> anyhow::Result<()> {
+    std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(|| loop {
+            FreeRtos::delay_ms(TICK_MS);
+            let beep_until = BEEP_UNTIL.clone();
+            let mut beep_until = beep_until.lock();
+            if beep_until.is_some_and(|until| Instant::now() >= until) {
+                *beep_until = None;
+                set(false);
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,167 @@
+// Per-client request accounting, for spotting a scan or an attack: every request is logged with
+// its method, path and remote IP and counted per IP in fixed client_window_secs windows.
+// A client above client_max_requests in a window is warned about once, and with client_block
+// its further requests in that window are answered 429 without reaching the handler.
+// Handlers registered with tracked_handler() are accounted, /ws is not.
+use embedded_svc::http::{server::Request, Method};
+use esp_idf_svc::{
+    hal::io::EspIOError,
+    http::server::{EspHttpConnection, EspHttpServer},
+    sys::EspError,
+};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use parking_lot::Mutex;
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{config, rate_limit::too_many_requests, web::peer_ip};
+
+// Clients tracked at once, the one with the oldest window is replaced by a new one
+const MAX_CLIENTS: usize = 16;
+// Clients listed in /health
+const TOP_CLIENTS: usize = 5;
+
+struct Client {
+    ip: IpAddr,
+    window_start: Instant,
+    // Requests in the current window
+    requests: u32,
+}
+
+lazy_static! {
+    static ref CLIENTS: Arc<Mutex<Vec<Client>>> = Arc::new(Mutex::new(Vec::new()));
+}
+
+// fn_handler() with per-client accounting in front of the handler
+pub trait TrackedServer {
+    fn tracked_handler<F>(
+        &mut self,
+        uri: &str,
+        method: Method,
+        handler: F,
+    ) -> Result<&mut Self, EspError>
+    where
+        F: for<'r> Fn(Request<&mut EspHttpConnection<'r>>) -> Result<(), EspIOError>
+            + Send
+            + 'static;
+}
+
+impl TrackedServer for EspHttpServer<'static> {
+    fn tracked_handler<F>(
+        &mut self,
+        uri: &str,
+        method: Method,
+        handler: F,
+    ) -> Result<&mut Self, EspError>
+    where
+        F: for<'r> Fn(Request<&mut EspHttpConnection<'r>>) -> Result<(), EspIOError>
+            + Send
+            + 'static,
+    {
+        self.fn_handler(uri, method, move |mut request| {
+            if !accept(&mut request) {
+                return too_many_requests(request);
+            }
+            handler(request)
+        })
+    }
+}
+
+// Log and count the request, false - the client is blocked for the rest of its window.
+// Only the path is logged, the query may carry the token
+fn accept(request: &mut Request<&mut EspHttpConnection>) -> bool {
+    let method = request.method();
+    let path = request
+        .uri()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let Some(ip) = peer_ip(request) else {
+        info!("{:?} {} from unknown address", method, path);
+        return true;
+    };
+    info!("{:?} {} from {}", method, path, ip);
+    let requests = count(ip);
+    let max_requests = config().client_max_requests;
+    if max_requests == 0 || requests <= max_requests {
+        return true;
+    }
+    if requests == max_requests + 1 {
+        warn!(
+            "{} sent more than {} requests in {} seconds{}",
+            ip,
+            max_requests,
+            config().client_window_secs,
+            if config().client_block {
+                ", blocked until the window ends"
+            } else {
+                ""
+            }
+        );
+    }
+    !config().client_block
+}
+
+// Count a request of ip in its current window, returns the requests in the window
+fn count(ip: IpAddr) -> u32 {
+    let window = Duration::from_secs(config().client_window_secs as u64);
+    let clients = CLIENTS.clone();
+    let mut clients = clients.lock();
+    let now = Instant::now();
+    let index = match clients.iter().position(|client| client.ip == ip) {
+        Some(index) => index,
+        None => {
+            let client = Client {
+                ip,
+                window_start: now,
+                requests: 0,
+            };
+            if clients.len() < MAX_CLIENTS {
+                clients.push(client);
+                clients.len() - 1
+            } else {
+                let oldest = (0..clients.len())
+                    .min_by_key(|&i| clients[i].window_start)
+                    .unwrap_or(0);
+                clients[oldest] = client;
+                oldest
+            }
+        }
+    };
+    let client = &mut clients[index];
+    if now.duration_since(client.window_start) >= window {
+        client.window_start = now;
+        client.requests = 0;
+    }
+    client.requests = client.requests.saturating_add(1);
+    client.requests
+}
+
+// Clients with most requests in their current window as JSON array like
+// [{"ip":"192.168.0.5","requests":12}], at most TOP_CLIENTS of them
+pub fn top_json() -> String {
+    let window = Duration::from_secs(config().client_window_secs as u64);
+    let clients = CLIENTS.clone();
+    let clients = clients.lock();
+    let mut top: Vec<&Client> = clients
+        .iter()
+        .filter(|client| client.window_start.elapsed() < window)
+        .collect();
+    top.sort_by(|a, b| b.requests.cmp(&a.requests));
+    let top: Vec<String> = top
+        .iter()
+        .take(TOP_CLIENTS)
+        .map(|client| {
+            format!(
+                "{{\"ip\":\"{}\",\"requests\":{}}}",
+                client.ip, client.requests
+            )
+        })
+        .collect();
+    format!("[{}]", top.join(","))
+}
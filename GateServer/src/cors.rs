@@ -0,0 +1,32 @@
+use embedded_svc::http::server::Request;
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+
+use crate::{config, CONFIG};
+
+// Headers allowing a dashboard served from another origin to call the API.
+// cors_origin is not validated, so the compiled config is used in the const
+const CORS_HEADERS: &[(&str, &str)] = &[
+    ("Access-Control-Allow-Origin", CONFIG.cors_origin),
+    ("Access-Control-Allow-Methods", "GET, POST, OPTIONS"),
+    (
+        "Access-Control-Allow-Headers",
+        "X-Gate-Token, Authorization",
+    ),
+    ("Access-Control-Max-Age", "600"),
+];
+
+// CORS headers for API responses, none if cors_enabled is off
+pub fn headers() -> &'static [(&'static str, &'static str)] {
+    if config().cors_enabled {
+        CORS_HEADERS
+    } else {
+        &[]
+    }
+}
+
+// Answer to the browser preflight OPTIONS request, no authentication:
+// the browser does not send X-Gate-Token or Authorization with it
+pub fn preflight(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    request.into_response(204, Some("No Content"), headers())?;
+    Ok(())
+}
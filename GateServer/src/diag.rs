@@ -0,0 +1,47 @@
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use log::{info, warn};
+
+use crate::auth::query_param;
+use crate::gate_io::{EspGateIo, GateIo};
+use crate::web::json_error;
+
+// Longest test pulse, enough to hear the relay click
+const MAX_PULSE_MS: u32 = 1000;
+
+// Test pulse of a relay from query parameters pin (open or sbs) and ms (1..1000)
+pub fn handle_relay(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let pin = query_param(request.uri(), "pin");
+    let ms = query_param(request.uri(), "ms").and_then(|ms| ms.parse::<u32>().ok());
+    let ms = match ms {
+        Some(ms) if (1..=MAX_PULSE_MS).contains(&ms) => ms,
+        _ => return bad_request(request, "ms must be 1..1000"),
+    };
+    match pin.as_deref() {
+        Some("open") => EspGateIo::MAIN.pulse_open(ms),
+        Some("sbs") => EspGateIo::MAIN.pulse_sbs(ms),
+        _ => return bad_request(request, "pin must be open or sbs"),
+    }
+    let pin = pin.unwrap_or_default();
+    info!("Diagnostic {} relay pulse of {} ms", pin, ms);
+    let mut response = request.into_ok_response()?;
+    response.write_all(format!("{{\"pin\":\"{}\",\"ms\":{}}}", pin, ms).as_bytes())?;
+    Ok(())
+}
+
+// Raw levels of the limit sensor inputs, without debounce and sensors_active_low
+pub fn handle_sensors(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let json = format!(
+        "{{\"opened\":{},\"closed\":{}}}",
+        EspGateIo::MAIN.opened_high(),
+        EspGateIo::MAIN.closed_high()
+    );
+    let mut response = request.into_ok_response()?;
+    response.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+fn bad_request(request: Request<&mut EspHttpConnection>, reason: &str) -> Result<(), EspIOError> {
+    warn!("Diagnostic relay pulse rejected: {}", reason);
+    json_error(request, 400, "bad_request", Some(reason))
+}
@@ -0,0 +1,112 @@
+// Configuration report for support: GET /config/effective returns what the unit runs with as
+// {"compiled":{...},"nvs":{...},"effective":{...}}. compiled is the validated cfg.toml built into
+// the firmware, nvs holds only the values stored by /config, BLE provisioning and /mode,
+// effective is the settings in use and the operating mode. Secrets are "********" if set.
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+
+use crate::settings::{self, masked};
+use crate::web::json_string;
+use crate::{config, cors, mode};
+
+fn compiled_json() -> String {
+    let c = config();
+    let secret = |value: &str| json_string(masked(value));
+    let fields: Vec<(&str, String)> = vec![
+        ("wifi_ssid", json_string(c.wifi_ssid)),
+        ("wifi_psk", secret(c.wifi_psk)),
+        ("auth_method", json_string(c.auth_method)),
+        ("gate_token", secret(c.gate_token)),
+        ("basic_user", json_string(c.basic_user)),
+        ("basic_pass", secret(c.basic_pass)),
+        ("allowed_ips", json_string(c.allowed_ips)),
+        ("auto_close_secs", c.auto_close_secs.to_string()),
+        ("sensor_samples", c.sensor_samples.to_string()),
+        ("sensors_active_low", c.sensors_active_low.to_string()),
+        ("gate2_pins", json_string(c.gate2_pins)),
+        ("status_led_pin", json_string(c.status_led_pin)),
+        ("status_led_ws2812", c.status_led_ws2812.to_string()),
+        ("buzzer_pin", json_string(c.buzzer_pin)),
+        ("warning_beep_ms", c.warning_beep_ms.to_string()),
+        ("warning_delay_ms", c.warning_delay_ms.to_string()),
+        ("ble_service_name", json_string(c.ble_service_name)),
+        ("ble_pop", secret(c.ble_pop)),
+        ("static_ip", json_string(c.static_ip)),
+        ("gateway", json_string(c.gateway)),
+        ("netmask", json_string(c.netmask)),
+        ("mdns_hostname", json_string(c.mdns_hostname)),
+        ("relay_active_low", c.relay_active_low.to_string()),
+        ("open_pulse_ms", c.open_pulse_ms.to_string()),
+        ("sbs_pulse_ms", c.sbs_pulse_ms.to_string()),
+        ("button_enabled", c.button_enabled.to_string()),
+        ("watchdog_secs", c.watchdog_secs.to_string()),
+        ("min_free_heap", c.min_free_heap.to_string()),
+        (
+            "gate_travel_timeout_secs",
+            c.gate_travel_timeout_secs.to_string(),
+        ),
+        ("expected_travel_ms", c.expected_travel_ms.to_string()),
+        ("client_max_requests", c.client_max_requests.to_string()),
+        ("client_window_secs", c.client_window_secs.to_string()),
+        ("client_block", c.client_block.to_string()),
+        (
+            "post_command_moving_ms",
+            c.post_command_moving_ms.to_string(),
+        ),
+        ("command_rssi", c.command_rssi.to_string()),
+        ("response_format", json_string(c.response_format)),
+        (
+            "min_command_interval_ms",
+            c.min_command_interval_ms.to_string(),
+        ),
+        ("sbs_cooldown_ms", c.sbs_cooldown_ms.to_string()),
+        ("gate_macro", json_string(c.gate_macro)),
+        ("mqtt_url", json_string(c.mqtt_url)),
+        ("mqtt_user", json_string(c.mqtt_user)),
+        ("mqtt_pass", secret(c.mqtt_pass)),
+        ("mqtt_topic", json_string(c.mqtt_topic)),
+        ("telegram_token", secret(c.telegram_token)),
+        ("telegram_chat_id", json_string(c.telegram_chat_id)),
+        ("diag_enabled", c.diag_enabled.to_string()),
+        ("cors_enabled", c.cors_enabled.to_string()),
+        ("cors_origin", json_string(c.cors_origin)),
+        ("http_port", c.http_port.to_string()),
+        ("https_enabled", c.https_enabled.to_string()),
+        // Certificate is public, but too long for a report
+        ("https_cert", secret(c.https_cert)),
+        ("https_key", secret(c.https_key)),
+        ("schedule_open", json_string(c.schedule_open)),
+        ("schedule_close", json_string(c.schedule_close)),
+        ("schedule_days", json_string(c.schedule_days)),
+        ("tz_offset_minutes", c.tz_offset_minutes.to_string()),
+        ("log_level", json_string(c.log_level)),
+        ("nvs_namespace", json_string(c.nvs_namespace)),
+        ("nvs_partition", json_string(c.nvs_partition)),
+    ];
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|(name, value)| format!("\"{}\":{}", name, value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn effective_json() -> String {
+    let settings = settings::current().to_json();
+    let settings = settings.strip_suffix('}').unwrap_or(&settings);
+    format!("{},\"mode\":\"{}\"}}", settings, mode::current().as_str())
+}
+
+pub fn json() -> String {
+    format!(
+        "{{\"compiled\":{},\"nvs\":{},\"effective\":{}}}",
+        compiled_json(),
+        settings::stored_json(),
+        effective_json()
+    )
+}
+
+pub fn handle(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let mut response = request.into_response(200, Some("OK"), cors::headers())?;
+    response.write_all(json().as_bytes())?;
+    Ok(())
+}
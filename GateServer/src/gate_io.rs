@@ -0,0 +1,94 @@
+// Gate hardware of the board: EspGateIo drives the pins of one of the gates. The GateIo trait and
+// the status logic on top of it are in the GateLogic crate, where they are unit tested.
+use esp_idf_hal::{delay::FreeRtos, gpio::Level};
+use log::error;
+use std::time::{Duration, Instant};
+
+pub use gate_logic::gate_io::{close, read_status, GateIo};
+
+use crate::{config, hardware, status_led, Gate};
+
+// Relay pin level: closed - contacts closed. Relays are driven active high,
+// active low with relay_active_low
+pub fn relay_level(closed: bool) -> Level {
+    if closed != config().relay_active_low {
+        Level::High
+    } else {
+        Level::Low
+    }
+}
+
+// Pins of the gate with this index in hardware().gates
+pub struct EspGateIo(pub usize);
+
+impl EspGateIo {
+    // Gate on the board.rs pins, the only one without gate2_pins
+    pub const MAIN: EspGateIo = EspGateIo(0);
+    pub const SECOND: EspGateIo = EspGateIo(1);
+
+    fn gate(&self) -> &'static Gate {
+        &hardware().gates[self.0]
+    }
+
+    // Relay was pulsed less than post_command_moving_ms ago
+    pub fn settling(&self) -> bool {
+        self.pulsed_within(Duration::from_millis(
+            config().post_command_moving_ms as u64,
+        ))
+    }
+
+    // Relay was pulsed less than window ago
+    pub fn pulsed_within(&self, window: Duration) -> bool {
+        self.gate()
+            .last_pulse
+            .clone()
+            .lock()
+            .is_some_and(|pulse| pulse.elapsed() < window)
+    }
+
+    fn record_pulse(&self) {
+        *self.gate().last_pulse.clone().lock() = Some(Instant::now());
+    }
+}
+
+impl GateIo for EspGateIo {
+    fn opened_high(&self) -> bool {
+        self.gate().opened.clone().lock().is_high()
+    }
+
+    fn closed_high(&self) -> bool {
+        self.gate().closed.clone().lock().is_high()
+    }
+
+    fn pulse_open(&self, ms: u32) {
+        let gate_open = self.gate().open.clone();
+        let mut gate_open = gate_open.lock();
+        status_led::flash();
+        if let Err(e) = gate_open.set_level(relay_level(true)) {
+            error!("Can not close gate open relay: {}", e);
+        }
+        FreeRtos::delay_ms(ms);
+        if let Err(e) = gate_open.set_level(relay_level(false)) {
+            error!("Can not release gate open relay: {}", e);
+        }
+        self.record_pulse();
+    }
+
+    fn pulse_sbs(&self, ms: u32) {
+        let gate_sbs = self.gate().sbs.clone();
+        let mut gate_sbs = gate_sbs.lock();
+        status_led::flash();
+        if let Err(e) = gate_sbs.set_level(relay_level(true)) {
+            error!("Can not close gate SBS relay: {}", e);
+        }
+        FreeRtos::delay_ms(ms);
+        if let Err(e) = gate_sbs.set_level(relay_level(false)) {
+            error!("Can not release gate SBS relay: {}", e);
+        }
+        self.record_pulse();
+    }
+
+    fn pause_ms(&self, ms: u32) {
+        FreeRtos::delay_ms(ms);
+    }
+}
@@ -0,0 +1,82 @@
+use esp_idf_hal::delay::FreeRtos;
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+use crate::gate_io::{EspGateIo, GateIo};
+use crate::{auto_close, config, gate_state::GateState, gate_status, health, status_reply, travel};
+
+// Longest relay pulse and pause of a step
+const MAX_PULSE_MS: u32 = 2000;
+const MAX_WAIT_MS: u32 = 30_000;
+
+#[derive(Clone, Copy)]
+enum Step {
+    Open(u32),
+    Sbs(u32),
+    Wait(u32),
+}
+
+lazy_static! {
+    /// Steps parsed from gate_macro, empty - /gate_macro is not served
+    static ref STEPS: Vec<Step> = match parse(config().gate_macro) {
+        Ok(steps) => steps,
+        Err(reason) => {
+            warn!("gate_macro {:?} ignored: {}", config().gate_macro, reason);
+            Vec::new()
+        }
+    };
+}
+
+// Comma separated steps like open:200,wait:500,sbs:200, durations in ms
+fn parse(sequence: &str) -> Result<Vec<Step>, &'static str> {
+    let mut steps = Vec::new();
+    for step in sequence
+        .split(',')
+        .map(str::trim)
+        .filter(|step| !step.is_empty())
+    {
+        let (kind, ms) = step.split_once(':').ok_or("step is not kind:ms")?;
+        let ms: u32 = ms.trim().parse().map_err(|_| "duration is not a number")?;
+        let step = match kind.trim() {
+            "open" | "sbs" if ms == 0 || ms > MAX_PULSE_MS => {
+                return Err("pulse must be 1..2000 ms");
+            }
+            "wait" if ms > MAX_WAIT_MS => return Err("wait must be up to 30000 ms"),
+            "open" => Step::Open(ms),
+            "sbs" => Step::Sbs(ms),
+            "wait" => Step::Wait(ms),
+            _ => return Err("step kind is not open, sbs or wait"),
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+// gate_macro is configured and valid
+pub fn enabled() -> bool {
+    !STEPS.is_empty()
+}
+
+// Macro command handler: relay pulses and pauses in order, replies with the final gate status.
+// Auto-close is armed if the macro has an open step
+pub fn run() -> &'static str {
+    if gate_status() == GateState::Fault {
+        warn!("Gate macro refused: limit sensor fault");
+        return status_reply(GateState::Fault);
+    }
+    info!("Gate macro of {} steps", STEPS.len());
+    auto_close::cancel();
+    for step in STEPS.iter() {
+        match *step {
+            Step::Open(ms) => EspGateIo::MAIN.pulse_open(ms),
+            Step::Sbs(ms) => EspGateIo::MAIN.pulse_sbs(ms),
+            Step::Wait(ms) => FreeRtos::delay_ms(ms),
+        }
+    }
+    health::record_action();
+    travel::start();
+    if STEPS.iter().any(|step| matches!(step, Step::Open(_))) {
+        auto_close::arm();
+    }
+    status_reply(gate_status())
+}
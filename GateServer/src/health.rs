@@ -0,0 +1,63 @@
+use esp_idf_hal::{delay::FreeRtos, reset};
+use esp_idf_svc::sys::{heap_caps_get_free_size, heap_caps_get_minimum_free_size, MALLOC_CAP_8BIT};
+use lazy_static::lazy_static;
+use log::warn;
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Instant};
+
+use crate::{clients, config, wifi::current_rssi, START_TIME};
+
+lazy_static! {
+    /// Time of the last relay pulse
+    static ref LAST_ACTION: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
+// Remember relay pulse time, called by open and SBS pulses
+pub fn record_action() {
+    *LAST_ACTION.clone().lock() = Some(Instant::now());
+}
+
+// Restart while the free heap is below min_free_heap (0 - never): a planned restart
+// instead of a crash on a failed allocation once fragmentation has eaten the heap
+pub fn check_heap() {
+    let floor = config().min_free_heap;
+    if floor == 0 {
+        return;
+    }
+    let free_heap = unsafe { heap_caps_get_free_size(MALLOC_CAP_8BIT) };
+    if free_heap >= floor as usize {
+        return;
+    }
+    warn!(
+        "Free heap {} bytes is below min_free_heap {}, restarting",
+        free_heap, floor
+    );
+    // Let the warning reach the log
+    FreeRtos::delay_ms(100);
+    reset::restart();
+}
+
+// Diagnostics in JSON
+// wifi - connected to AP, rssi - WiFi signal strength, free_heap/min_free_heap - current and
+// lowest since start free heap in bytes, uptime - seconds since start,
+// last_action - uptime of the last relay pulse, null - no pulses yet,
+// top_clients - IPs with most requests in the current client_window_secs
+pub fn json() -> String {
+    let rssi = current_rssi();
+    let free_heap = unsafe { heap_caps_get_free_size(MALLOC_CAP_8BIT) };
+    let min_free_heap = unsafe { heap_caps_get_minimum_free_size(MALLOC_CAP_8BIT) };
+    let last_action = match *LAST_ACTION.clone().lock() {
+        Some(time) => time.duration_since(*START_TIME).as_secs().to_string(),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"wifi\":{},\"rssi\":{},\"free_heap\":{},\"min_free_heap\":{},\"uptime\":{},\"last_action\":{},\"top_clients\":{}}}",
+        rssi.is_some(),
+        rssi.map_or("null".to_string(), |rssi| rssi.to_string()),
+        free_heap,
+        min_free_heap,
+        START_TIME.elapsed().as_secs(),
+        last_action,
+        clients::top_json()
+    )
+}
@@ -0,0 +1,77 @@
+use esp_idf_svc::{
+    hal::io::EspIOError,
+    http::server::{Configuration, EspHttpServer},
+    tls::X509,
+};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use std::ffi::CString;
+
+use crate::config;
+
+lazy_static! {
+    /// Server certificate and private key in PEM, NUL terminated for ESP-IDF.
+    /// None if https_enabled is off or the configured PEM is empty.
+    static ref TLS_KEYS: Option<(CString, CString)> = tls_keys();
+}
+
+fn tls_keys() -> Option<(CString, CString)> {
+    if !config().https_enabled {
+        return None;
+    }
+    if config().https_cert.is_empty() || config().https_key.is_empty() {
+        warn!("https_enabled is set, but https_cert or https_key is empty");
+        return None;
+    }
+    match (
+        CString::new(config().https_cert),
+        CString::new(config().https_key),
+    ) {
+        (Ok(cert), Ok(key)) => Some((cert, key)),
+        _ => {
+            error!("https_cert or https_key contains NUL character");
+            None
+        }
+    }
+}
+
+// Handlers registered in main() with two gates, gate_macro, diagnostics and CORS are above
+// the default 32
+const MAX_URI_HANDLERS: usize = 56;
+
+// Port of the plain HTTP server from http_port, 0 is replaced with 80 by Config::validate()
+pub fn http_port() -> u16 {
+    config().http_port
+}
+
+// Start HTTPS server on port 443 if enabled, otherwise or if TLS can not be started -
+// plain HTTP server on http_port, so the gate stays operable
+pub fn start_server() -> Result<EspHttpServer<'static>, EspIOError> {
+    if let Some((cert, key)) = TLS_KEYS.as_ref() {
+        // Each TLS session allocates its own buffers, so allow fewer sockets than plain HTTP
+        let conf = Configuration {
+            max_open_sockets: 4,
+            max_uri_handlers: MAX_URI_HANDLERS,
+            server_certificate: Some(X509::pem(cert)),
+            private_key: Some(X509::pem(key)),
+            ..Default::default()
+        };
+        match EspHttpServer::new(&conf) {
+            Ok(server) => {
+                info!("HTTPS server started on port {}", conf.https_port);
+                return Ok(server);
+            }
+            Err(e) => error!("Can not start HTTPS server, falling back to HTTP: {}", e),
+        }
+    }
+    // WebSocket sessions keep sockets open, so allow more than the default 4
+    let conf = Configuration {
+        http_port: http_port(),
+        max_open_sockets: 7,
+        max_uri_handlers: MAX_URI_HANDLERS,
+        ..Default::default()
+    };
+    let server = EspHttpServer::new(&conf)?;
+    info!("HTTP server started on port {}", conf.http_port);
+    Ok(server)
+}
@@ -0,0 +1,85 @@
+// IPv6 on the station interface, built with feature "ipv6".
+// Needs CONFIG_LWIP_IPV6 and, for a global address from router advertisements,
+// CONFIG_LWIP_IPV6_AUTOCONFIG in sdkconfig. HTTP server listens on a dual-stack socket then.
+use std::net::Ipv6Addr;
+
+#[cfg(feature = "ipv6")]
+use esp_idf_svc::{
+    handle::RawHandle,
+    netif::EspNetif,
+    sys::{self, esp},
+};
+
+// Addresses of the station interface are looked up by its key, so no handle is kept
+#[cfg(feature = "ipv6")]
+const STA_IFKEY: &core::ffi::CStr = c"WIFI_STA_DEF";
+// Global address may arrive a bit later than the link-local one
+#[cfg(feature = "ipv6")]
+const ADDRESS_WAIT_MS: u32 = 3000;
+
+// Start IPv6 on the connected station interface and log the obtained addresses
+#[cfg(feature = "ipv6")]
+pub fn enable(netif: &EspNetif) -> anyhow::Result<()> {
+    use esp_idf_hal::delay::FreeRtos;
+    use log::info;
+
+    esp!(unsafe { sys::esp_netif_create_ip6_linklocal(netif.handle()) })?;
+    let mut waited_ms = 0;
+    let mut addrs = addresses();
+    while !addrs.iter().any(is_global) && waited_ms < ADDRESS_WAIT_MS {
+        FreeRtos::delay_ms(500);
+        waited_ms += 500;
+        addrs = addresses();
+    }
+    if addrs.is_empty() {
+        info!("No IPv6 address yet");
+    }
+    for addr in addrs {
+        let scope = if is_global(&addr) {
+            "global"
+        } else {
+            "link-local"
+        };
+        info!("IPv6 {} address {}", scope, addr);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "ipv6"))]
+pub fn enable<T>(_netif: &T) -> anyhow::Result<()> {
+    Ok(())
+}
+
+// IPv6 addresses of the station interface, empty - not connected or built without IPv6
+#[cfg(feature = "ipv6")]
+pub fn addresses() -> Vec<Ipv6Addr> {
+    let netif = unsafe { sys::esp_netif_get_handle_from_ifkey(STA_IFKEY.as_ptr()) };
+    if netif.is_null() {
+        return Vec::new();
+    }
+    let mut raw: [sys::esp_ip6_addr_t; sys::LWIP_IPV6_NUM_ADDRESSES as usize] =
+        unsafe { core::mem::zeroed() };
+    let count = unsafe { sys::esp_netif_get_all_ip6(netif, raw.as_mut_ptr()) };
+    raw.iter()
+        .take(count.max(0) as usize)
+        .map(|addr| {
+            // Words are in network byte order, so their memory bytes are the octets
+            let mut octets = [0u8; 16];
+            for (chunk, word) in octets.chunks_mut(4).zip(addr.addr) {
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            Ipv6Addr::from(octets)
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "ipv6"))]
+pub fn addresses() -> Vec<Ipv6Addr> {
+    Vec::new()
+}
+
+#[cfg(feature = "ipv6")]
+fn is_global(addr: &Ipv6Addr) -> bool {
+    // fe80::/10 is link-local
+    addr.segments()[0] & 0xffc0 != 0xfe80
+}
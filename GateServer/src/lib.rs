@@ -0,0 +1,1168 @@
+use anyhow::Context;
+use embedded_svc::{
+    http::{server::Request, Method},
+    io::Write,
+};
+use esp_idf_hal::{
+    delay::FreeRtos,
+    gpio::*,
+    peripheral::Peripheral,
+    peripherals::Peripherals,
+    reset,
+    task::watchdog::{TWDTConfig, TWDTDriver},
+};
+use esp_idf_svc::{
+    hal::io::EspIOError,
+    http::server::EspHttpConnection,
+    mdns::EspMdns,
+    nvs::EspDefaultNvsPartition,
+    sntp::EspSntp,
+    sys::{esp, gpio_mode_t_GPIO_MODE_OUTPUT, gpio_set_direction, gpio_set_level, EspError},
+};
+use gate_logic::status::{reported, StatusReport};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::{
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use crate::access_log::Action;
+use crate::auth::{authorized, is_authorized, unauthorized};
+use crate::clients::TrackedServer;
+use crate::gate_io::{EspGateIo, GateIo};
+use crate::gate_state::GateState;
+use crate::rate_limit::too_many_requests;
+use crate::response_format::ResponseFormat;
+use crate::web::{
+    favicon, json_error, json_str_field, method_not_allowed, peer_ip, read_body, HTML_HEADERS,
+};
+use crate::wifi::{connect_wifi, current_rssi};
+
+pub mod access_log;
+pub mod allowlist;
+pub mod auth;
+pub mod auto_close;
+#[cfg(feature = "ble")]
+pub mod ble_provisioning;
+pub mod board;
+pub mod button;
+pub mod buzzer;
+pub mod clients;
+pub mod cors;
+pub mod diag;
+pub mod effective_config;
+pub mod gate_io;
+pub mod gate_macro;
+pub use gate_logic::gate_state;
+pub mod health;
+pub mod https;
+pub mod ipv6;
+pub mod log_level;
+pub mod maintenance;
+pub mod metrics;
+pub mod mode;
+pub mod mqtt;
+pub mod ota;
+pub mod rate_limit;
+pub mod remote;
+pub mod response_format;
+#[path = "../../common/rgb_led.rs"]
+pub mod rgb_led;
+pub mod schedule;
+pub mod sensors;
+pub mod settings;
+pub mod status_led;
+pub mod storage;
+pub mod telegram;
+pub mod travel;
+pub mod validation;
+pub mod web;
+pub mod wifi;
+pub mod wifi_scan;
+#[path = "../../common/wifi_setup.rs"]
+pub mod wifi_setup;
+pub mod ws;
+
+// Peripherals, gate pins and NVS partition, set by init_peripherals() at start
+pub struct Hardware {
+    /// Peripherals for drivers created later: WiFi modem, watchdog, button
+    pub peripherals: Arc<Mutex<Peripherals>>,
+    /// Main gate on the board.rs pins, then the second one on gate2_pins, if configured
+    pub gates: Vec<Gate>,
+    /// Default NVS partition, shared by WiFi and storage.rs without nvs_partition
+    pub nvs_partition: EspDefaultNvsPartition,
+}
+
+/// Relays and limit sensors of one gate
+pub struct Gate {
+    /// Gate open pin
+    pub open: Arc<Mutex<PinDriver<'static, AnyOutputPin, Output>>>,
+    /// Gate step-by-step (SBS) pin
+    /// When opened - then close, When closed - then open, in porgress - stop
+    pub sbs: Arc<Mutex<PinDriver<'static, AnyOutputPin, Output>>>,
+    /// Gate opened sensor (active high by default, see sensors_active_low)
+    /// Internal pull-up keeps the line high while the sensor does not pull it low
+    pub opened: Arc<Mutex<PinDriver<'static, AnyIOPin, Input>>>,
+    /// Gate closed sensor (active high by default, see sensors_active_low)
+    pub closed: Arc<Mutex<PinDriver<'static, AnyIOPin, Input>>>,
+    /// Time of the last relay pulse, for post_command_moving_ms
+    pub last_pulse: Arc<Mutex<Option<Instant>>>,
+}
+
+static HARDWARE: OnceLock<Hardware> = OnceLock::new();
+
+// Take peripherals, set up gate pins, the status LED, the buzzer and NVS. The error tells which
+// of them has failed. Invalid gate2_pins only leave the second gate out and an invalid
+// status_led_pin or buzzer_pin the LED or the buzzer, so a config typo does not stop the main gate
+fn init_peripherals() -> anyhow::Result<()> {
+    let mut peripherals = Peripherals::take().context("Can not take peripherals")?;
+    let main_gate = init_gate(
+        board::gate_open_pin(&mut peripherals).downgrade_output(),
+        board::gate_sbs_pin(&mut peripherals).downgrade_output(),
+        board::gate_opened_pin(&mut peripherals).downgrade(),
+        board::gate_closed_pin(&mut peripherals).downgrade(),
+    )
+    .context("Can not set up gate pins")?;
+    let mut gates = vec![main_gate];
+    match board::gate2_pins(config().gate2_pins) {
+        Ok(Some((open, sbs, opened, closed))) => match init_gate(open, sbs, opened, closed) {
+            Ok(gate) => {
+                info!("Second gate on GPIOs {}", config().gate2_pins);
+                gates.push(gate);
+            }
+            Err(e) => error!("Can not set up second gate pins: {:#}", e),
+        },
+        Ok(None) => {}
+        Err(e) => error!("Invalid gate2_pins, second gate disabled: {}", e),
+    }
+    match board::status_led_pin(config().status_led_pin, config().gate2_pins) {
+        Ok(Some(pin)) => {
+            if let Err(e) = status_led::init(pin, board::status_led_channel(&mut peripherals)) {
+                error!("Can not set up status LED: {}", e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => error!("Invalid status_led_pin, status LED disabled: {}", e),
+    }
+    match board::buzzer_pin(
+        config().buzzer_pin,
+        config().gate2_pins,
+        config().status_led_pin,
+    ) {
+        Ok(Some(pin)) => {
+            if let Err(e) = buzzer::init(pin) {
+                error!("Can not set up buzzer: {}", e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => error!("Invalid buzzer_pin, buzzer disabled: {}", e),
+    }
+    let nvs_partition = EspDefaultNvsPartition::take().context("Can not take NVS partition")?;
+    let hardware = Hardware {
+        peripherals: Arc::new(Mutex::new(peripherals)),
+        gates,
+        nvs_partition,
+    };
+    HARDWARE
+        .set(hardware)
+        .map_err(|_| anyhow::anyhow!("Peripherals are already initialized"))
+}
+
+// Relay outputs and limit sensor inputs with pull-ups of one gate
+fn init_gate(
+    open: AnyOutputPin,
+    sbs: AnyOutputPin,
+    opened: AnyIOPin,
+    closed: AnyIOPin,
+) -> anyhow::Result<Gate> {
+    let open = relay_output(open).context("Can not set up gate open relay pin")?;
+    let sbs = relay_output(sbs).context("Can not set up gate SBS relay pin")?;
+    let mut opened = PinDriver::input(opened).context("Can not set up gate opened sensor pin")?;
+    opened
+        .set_pull(Pull::Up)
+        .context("Can not enable gate opened sensor pull-up")?;
+    let mut closed = PinDriver::input(closed).context("Can not set up gate closed sensor pin")?;
+    closed
+        .set_pull(Pull::Up)
+        .context("Can not enable gate closed sensor pull-up")?;
+    Ok(Gate {
+        open: Arc::new(Mutex::new(open)),
+        sbs: Arc::new(Mutex::new(sbs)),
+        opened: Arc::new(Mutex::new(opened)),
+        closed: Arc::new(Mutex::new(closed)),
+        last_pulse: Arc::new(Mutex::new(None)),
+    })
+}
+
+// Relay output released from the start: the inactive level is latched before the pin becomes
+// an output, so an active low relay is not pulsed on boot
+fn relay_output(pin: AnyOutputPin) -> anyhow::Result<PinDriver<'static, AnyOutputPin, Output>> {
+    let released = gate_io::relay_level(false);
+    esp!(unsafe { gpio_set_level(pin.pin(), (released == Level::High) as u32) })?;
+    let mut relay = PinDriver::output(pin)?;
+    relay.set_level(released)?;
+    Ok(relay)
+}
+
+// Drive all relay outputs to the released level first thing after power-up or a brownout reset,
+// so the pins never float while the rest starts: a floating or low pin could pulse a relay.
+// CONFIG is read directly, as config() validation logs and the logger is not up yet.
+// The pins are taken over by their drivers in init_peripherals() at the same level
+fn release_relays() -> Vec<(i32, Result<(), EspError>)> {
+    // Released is high for active low relays, see gate_io::relay_level
+    let released = CONFIG.relay_active_low as u32;
+    board::relay_gpios(CONFIG.gate2_pins)
+        .into_iter()
+        .map(|gpio| {
+            let result = esp!(unsafe { gpio_set_level(gpio, released) }).and_then(|_| {
+                esp!(unsafe { gpio_set_direction(gpio, gpio_mode_t_GPIO_MODE_OUTPUT) })
+            });
+            (gpio, result)
+        })
+        .collect()
+}
+
+// Hardware set up at start
+pub fn hardware() -> &'static Hardware {
+    HARDWARE
+        .get()
+        .expect("init_peripherals() is called first in main")
+}
+
+static VALID_CONFIG: OnceLock<Config> = OnceLock::new();
+
+// Compiled config after Config::validate(), use it instead of CONFIG
+pub fn config() -> &'static Config {
+    VALID_CONFIG.get_or_init(|| CONFIG.validate())
+}
+
+lazy_static! {
+    /// Firmware start time for uptime reporting
+    pub static ref START_TIME: Instant = Instant::now();
+    /// Time of the last SBS relay pulse, for sbs_cooldown_ms
+    static ref LAST_SBS_PULSE: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
+// Free heap is checked against min_free_heap this often
+const HEAP_CHECK_SECS: u32 = 10;
+
+// Gate commands: URI, name for logs, logged action and handler
+const COMMANDS: [(&str, &str, Action, fn() -> &'static str); 4] = [
+    ("/gate_sbs", "SBS", Action::Sbs, gate_sbs),
+    ("/gate_open", "open", Action::Open, gate_open),
+    ("/gate_close", "close", Action::Close, gate_close),
+    ("/gate_macro", "macro", Action::Macro, gate_macro::run),
+];
+
+// WiFi AP credentials
+#[toml_cfg::toml_config]
+pub struct Config {
+    #[default("")]
+    wifi_ssid: &'static str,
+    #[default("")]
+    wifi_psk: &'static str,
+    // WiFi auth method: wpa2, wpa3, wpa2wpa3 or none, empty - none without password, else wpa2
+    #[default("")]
+    auth_method: &'static str,
+    // Shared secret for command endpoints, empty - no authentication
+    #[default("")]
+    gate_token: &'static str,
+    // HTTP Basic Auth credentials accepted besides gate_token, empty basic_user - no Basic Auth
+    #[default("")]
+    basic_user: &'static str,
+    #[default("")]
+    basic_pass: &'static str,
+    // Comma separated IPs and networks like 192.168.0.10,192.168.0.64/28 allowed to send gate
+    // commands besides the token check, empty - any address
+    #[default("")]
+    allowed_ips: &'static str,
+    // Close the gate automatically after opening, 0 - disabled
+    #[default(0)]
+    auto_close_secs: u32,
+    // Number of reads per sensor, majority decides the sensor level
+    #[default(5)]
+    sensor_samples: u8,
+    // Sensors pull the input to GND when triggered (e.g. reed switches)
+    #[default(false)]
+    sensors_active_low: bool,
+    // Second gate GPIOs "open,sbs,opened,closed" like "5,6,7,20", empty - single gate
+    #[default("")]
+    gate2_pins: &'static str,
+    // Status LED GPIO like "8", empty - no LED
+    #[default("")]
+    status_led_pin: &'static str,
+    // Status LED is a WS2812 RGB LED, false - plain LED, active high
+    #[default(false)]
+    status_led_ws2812: bool,
+    // Warning buzzer GPIO like "6", active high, sounded before open and SBS pulses. Empty - no buzzer
+    #[default("")]
+    buzzer_pin: &'static str,
+    #[default(1000)]
+    warning_beep_ms: u32,
+    // Relay pulse is delayed this long from the beep start
+    #[default(1000)]
+    warning_delay_ms: u32,
+    // BLE provisioning (feature ble) service name and proof of possession for the phone app
+    #[default("GateServer")]
+    ble_service_name: &'static str,
+    #[default("gatesetup")]
+    ble_pop: &'static str,
+    // Static IP address, empty - use DHCP
+    #[default("")]
+    static_ip: &'static str,
+    #[default("")]
+    gateway: &'static str,
+    #[default("255.255.255.0")]
+    netmask: &'static str,
+    // mDNS host name, the server is reachable as <mdns_hostname>.local
+    #[default("gate")]
+    mdns_hostname: &'static str,
+    // Relay modules switched on by a low level, most optocoupler boards. Relays are active high otherwise
+    #[default(false)]
+    relay_active_low: bool,
+    // Relay contact closure time for open and SBS commands
+    #[default(200)]
+    open_pulse_ms: u32,
+    #[default(200)]
+    sbs_pulse_ms: u32,
+    // Local SBS button on GPIO4 to GND
+    #[default(false)]
+    button_enabled: bool,
+    // Reboot if the main loop is stuck while WiFi is connected, 0 - disabled
+    #[default(30)]
+    watchdog_secs: u32,
+    // Restart when free heap falls below this many bytes, 0 - disabled
+    #[default(0)]
+    min_free_heap: u32,
+    // Time for the gate to reach a limit after a relay pulse, 0 - not watched
+    #[default(30)]
+    gate_travel_timeout_secs: u32,
+    // Gate is reported moving this long after a relay pulse unless a limit is reached,
+    // 0 - by the sensors only
+    #[default(0)]
+    expected_travel_ms: u32,
+    // Requests per client IP in client_window_secs before a warning, 0 - no limit
+    #[default(0)]
+    client_max_requests: u32,
+    #[default(60)]
+    client_window_secs: u32,
+    // Answer 429 to a client above client_max_requests until its window ends
+    #[default(false)]
+    client_block: bool,
+    // Gate is reported moving this long after a relay pulse, before the sensors follow
+    #[default(2000)]
+    post_command_moving_ms: u32,
+    // Add the server's WiFi RSSI to gate command replies
+    #[default(false)]
+    command_rssi: bool,
+    // Gate command reply body: compact, status or text
+    #[default("compact")]
+    response_format: &'static str,
+    // Minimal interval between accepted gate commands
+    #[default(1000)]
+    min_command_interval_ms: u32,
+    // SBS commands (HTTP, button, MQTT) within this time after an SBS pulse are ignored
+    #[default(2000)]
+    sbs_cooldown_ms: u32,
+    // Relay sequence for /gate_macro like open:200,wait:500,sbs:200 (ms), empty - no macro
+    #[default("")]
+    gate_macro: &'static str,
+    // MQTT broker like mqtt://192.168.0.2:1883, empty - MQTT disabled
+    #[default("")]
+    mqtt_url: &'static str,
+    #[default("")]
+    mqtt_user: &'static str,
+    #[default("")]
+    mqtt_pass: &'static str,
+    // State is published to <mqtt_topic>/state, commands are received from <mqtt_topic>/set
+    #[default("gate")]
+    mqtt_topic: &'static str,
+    // Telegram bot token and chat for gate state messages, empty - no messages
+    #[default("")]
+    telegram_token: &'static str,
+    #[default("")]
+    telegram_chat_id: &'static str,
+    // /diag/relay and /diag/sensors for checking the wiring on installation
+    #[default(false)]
+    diag_enabled: bool,
+    // Allow browser pages from other origins (e.g. a dashboard) to call the JSON API
+    #[default(false)]
+    cors_enabled: bool,
+    // Value of Access-Control-Allow-Origin, like http://dashboard.local
+    #[default("*")]
+    cors_origin: &'static str,
+    // Plain HTTP server port
+    #[default(80)]
+    http_port: u16,
+    // HTTPS on port 443 instead of HTTP, needs https_cert and https_key
+    #[default(false)]
+    https_enabled: bool,
+    // Server certificate and private key in PEM
+    #[default("")]
+    https_cert: &'static str,
+    #[default("")]
+    https_key: &'static str,
+    // Daily open and close times HH:MM in local time, empty - no scheduled event
+    #[default("")]
+    schedule_open: &'static str,
+    #[default("")]
+    schedule_close: &'static str,
+    // Scheduled days of week, 1 - Monday .. 7 - Sunday
+    #[default("1234567")]
+    schedule_days: &'static str,
+    // Local time offset from UTC
+    #[default(0)]
+    tz_offset_minutes: i32,
+    // Log verbosity: off, error, warn, info or debug, changed live by POST /loglevel
+    #[default("info")]
+    log_level: &'static str,
+    // Prefix of the NVS namespaces, see storage.rs
+    #[default("gate")]
+    nvs_namespace: &'static str,
+    // NVS data partition label, empty - the default nvs partition
+    #[default("")]
+    nvs_partition: &'static str,
+}
+
+// Server role, run by the GateServer binary and by Gate with role = "server"
+pub fn run() -> anyhow::Result<()> {
+    esp_idf_svc::sys::link_patches();
+    let relays = release_relays();
+    esp_idf_svc::log::EspLogger::initialize_default();
+    for (gpio, result) in relays {
+        match result {
+            Ok(()) => info!("Relay output GPIO{} released at boot", gpio),
+            Err(e) => error!("Can not release relay output GPIO{}: {}", gpio, e),
+        }
+    }
+
+    lazy_static::initialize(&START_TIME);
+    // Config problems are logged before anything uses the config
+    config();
+    log_level::init();
+    if let Err(e) = init_peripherals() {
+        // Delay keeps a wiring or pin conflict problem from flooding the log with restarts
+        error!(
+            "Peripheral initialization failed, restarting in 10 seconds: {:?}",
+            e
+        );
+        FreeRtos::delay_ms(10_000);
+        reset::restart();
+    }
+    lazy_static::initialize(&settings::SETTINGS);
+    // Report a malformed gate_macro at startup rather than on the first command
+    gate_macro::enabled();
+    allowlist::init();
+    access_log::init();
+    let app_config = config();
+    if !auth::auth_required() {
+        warn!("gate_token and basic_user are empty, command endpoints are not protected");
+    }
+    // auto_close_secs may be changed at runtime, so the timer task always runs
+    auto_close::spawn_task()?;
+    sensors::spawn_task()?;
+    if app_config.gate_travel_timeout_secs > 0 || app_config.expected_travel_ms > 0 {
+        travel::spawn_task()?;
+    }
+    if app_config.button_enabled {
+        button::spawn_task()?;
+    }
+    if telegram::enabled() {
+        telegram::spawn_task()?;
+    }
+    if status_led::enabled() {
+        status_led::spawn_task()?;
+    }
+    if buzzer::enabled() {
+        buzzer::spawn_task()?;
+    }
+    // SNTP client runs in background for the whole program life, it syncs once WiFi is up
+    let _sntp = if schedule::enabled() {
+        schedule::spawn_task()?;
+        Some(EspSntp::new_default()?)
+    } else {
+        None
+    };
+    let mut watchdog_driver = if app_config.watchdog_secs > 0 {
+        let peripherals = hardware().peripherals.clone();
+        let mut peripherals = peripherals.lock();
+        Some(TWDTDriver::new(
+            unsafe { peripherals.twdt.clone_unchecked() },
+            &TWDTConfig {
+                duration: Duration::from_secs(app_config.watchdog_secs as u64),
+                panic_on_trigger: true,
+                ..Default::default()
+            },
+        )?)
+    } else {
+        None
+    };
+    #[cfg(feature = "ble")]
+    if ble_provisioning::needed() {
+        if let Err(e) = ble_provisioning::run() {
+            error!("BLE provisioning failed: {}", e);
+        }
+    }
+    loop {
+        // Reconnect loop, then WiFi connection lost
+        'reconnect_loop: {
+            let (wifi_ssid, wifi_psk) = settings::wifi_credentials();
+            let mut wifi = connect_wifi(&wifi_ssid, &wifi_psk).unwrap();
+            status_led::set_connected(true);
+            // Main task is watched only while connected, scanning for a missing AP may take long.
+            // Subscription is dropped, so the task is unwatched, when leaving the block.
+            let mut watchdog = watchdog_driver
+                .as_mut()
+                .map(|driver| driver.watch_current_task())
+                .transpose()?;
+            // mDNS responder lives in this block, so it is freed and registered again on reconnect
+            let mdns = start_mdns(app_config.mdns_hostname)?;
+            if let Err(e) = mqtt::start() {
+                error!("Can not start MQTT client: {}", e);
+            }
+            info!("Starting network services");
+            let mut server = https::start_server()?;
+            // Main page handler
+            server.tracked_handler(
+                "/",
+                Method::Get,
+                |request| -> core::result::Result<(), EspIOError> {
+                    info!("Gate main page called");
+                    let html = gate_page();
+                    let mut response = request.into_response(200, Some("OK"), HTML_HEADERS)?;
+                    response.write_all(html.as_bytes())?;
+                    Ok(())
+                },
+            )?;
+            // Browser tab icon
+            server.tracked_handler("/favicon.ico", Method::Get, favicon)?;
+            // Gate status JSON handler
+            server.tracked_handler(
+                "/gate_status",
+                Method::Get,
+                |request| -> core::result::Result<(), EspIOError> {
+                    info!("Gate status called");
+                    let html = gate_json_status();
+                    let mut response = request.into_response(200, Some("OK"), cors::headers())?;
+                    response.write_all(html.as_bytes())?;
+                    Ok(())
+                },
+            )?;
+            // Diagnostics JSON handler
+            server.tracked_handler(
+                "/health",
+                Method::Get,
+                |request| -> core::result::Result<(), EspIOError> {
+                    info!("Health called");
+                    let html = health::json();
+                    let mut response = request.into_response(200, Some("OK"), cors::headers())?;
+                    response.write_all(html.as_bytes())?;
+                    Ok(())
+                },
+            )?;
+            // Prometheus metrics handler
+            server.tracked_handler(
+                "/metrics",
+                Method::Get,
+                |request| -> core::result::Result<(), EspIOError> {
+                    info!("Metrics called");
+                    let text = metrics::text();
+                    let mut response = request.into_response(
+                        200,
+                        Some("OK"),
+                        &[("Content-Type", "text/plain; version=0.0.4")],
+                    )?;
+                    response.write_all(text.as_bytes())?;
+                    Ok(())
+                },
+            )?;
+            // Gate command handlers, POST to operate the gate.
+            // GET alias is accepted only with a token, see handle_command_get
+            // Macro is served only if gate_macro is configured
+            for (uri, name, action, command) in COMMANDS {
+                if action == Action::Macro && !gate_macro::enabled() {
+                    continue;
+                }
+                server.tracked_handler(
+                    uri,
+                    Method::Post,
+                    move |request| -> core::result::Result<(), EspIOError> {
+                        handle_command(request, name, action, command)
+                    },
+                )?;
+                server.tracked_handler(
+                    uri,
+                    Method::Get,
+                    move |request| -> core::result::Result<(), EspIOError> {
+                        handle_command_get(request, name, action, command)
+                    },
+                )?;
+            }
+            // Per gate URIs /gate/<id>/open, /gate/<id>/sbs and /gate/<id>/status, 1 - main gate
+            for id in 1..=hardware().gates.len() {
+                let status: fn() -> String = if id == 1 {
+                    gate_json_status
+                } else {
+                    gate2_json_status
+                };
+                for (command, action, run) in gate_commands(id) {
+                    let name = format!("{} {}", id, command);
+                    server.tracked_handler(
+                        &format!("/gate/{}/{}", id, command),
+                        Method::Post,
+                        move |request| -> core::result::Result<(), EspIOError> {
+                            handle_gate_command(request, &name, action, run, status)
+                        },
+                    )?;
+                }
+                server.tracked_handler(
+                    &format!("/gate/{}/status", id),
+                    Method::Get,
+                    move |request| -> core::result::Result<(), EspIOError> {
+                        info!("Gate {} status called", id);
+                        let json = if id == 1 {
+                            gate_json_status()
+                        } else {
+                            gate2_json_status()
+                        };
+                        let mut response =
+                            request.into_response(200, Some("OK"), cors::headers())?;
+                        response.write_all(json.as_bytes())?;
+                        Ok(())
+                    },
+                )?;
+            }
+            // Gate command by name in JSON body, same as the command URIs
+            server.tracked_handler(
+                "/command",
+                Method::Post,
+                |request| -> core::result::Result<(), EspIOError> { handle_json_command(request) },
+            )?;
+            // GateControl heartbeat, its last time and RSSI are reported in the gate status
+            server.tracked_handler(
+                "/ping",
+                Method::Get,
+                authorized("Ping", remote::handle_ping),
+            )?;
+            // Access log JSON handler
+            server.tracked_handler(
+                "/log",
+                Method::Get,
+                authorized(
+                    "Access log",
+                    |request| -> core::result::Result<(), EspIOError> {
+                        let json = access_log::json();
+                        let mut response = request.into_ok_response()?;
+                        response.write_all(json.as_bytes())?;
+                        Ok(())
+                    },
+                ),
+            )?;
+            // Commissioning diagnostics, disabled in production by diag_enabled
+            if app_config.diag_enabled {
+                server.tracked_handler(
+                    "/diag/relay",
+                    Method::Post,
+                    authorized("Diagnostic relay", diag::handle_relay),
+                )?;
+                server.tracked_handler(
+                    "/diag/sensors",
+                    Method::Get,
+                    authorized("Diagnostic sensors", diag::handle_sensors),
+                )?;
+            }
+            // CORS preflight handlers for the JSON API
+            if app_config.cors_enabled {
+                for uri in [
+                    "/gate_status",
+                    "/health",
+                    "/gate_sbs",
+                    "/gate_open",
+                    "/gate_close",
+                    "/command",
+                ] {
+                    server.tracked_handler(uri, Method::Options, cors::preflight)?;
+                }
+                for id in 1..=hardware().gates.len() {
+                    for command in ["open", "sbs", "status"] {
+                        let uri = format!("/gate/{}/{}", id, command);
+                        server.tracked_handler(&uri, Method::Options, cors::preflight)?;
+                    }
+                }
+            }
+            // Firmware update handler
+            server.tracked_handler(
+                "/ota",
+                Method::Post,
+                authorized("Firmware update", ota::handle_update),
+            )?;
+            // Settings editor page
+            server.tracked_handler(
+                "/settings",
+                Method::Get,
+                authorized(
+                    "Settings page",
+                    |request| -> core::result::Result<(), EspIOError> {
+                        let html = settings::page();
+                        let mut response = request.into_response(200, Some("OK"), HTML_HEADERS)?;
+                        response.write_all(html.as_bytes())?;
+                        Ok(())
+                    },
+                ),
+            )?;
+            // Compiled, stored and effective configuration handler
+            server.tracked_handler(
+                "/config/effective",
+                Method::Get,
+                authorized("Effective config", effective_config::handle),
+            )?;
+            // Runtime settings update handler
+            server.tracked_handler(
+                "/config",
+                Method::Post,
+                authorized("Settings update", settings::handle_update),
+            )?;
+            // Reboot handler
+            server.tracked_handler(
+                "/restart",
+                Method::Post,
+                authorized("Restart", maintenance::handle_restart),
+            )?;
+            // Factory reset handler
+            server.tracked_handler(
+                "/factory_reset",
+                Method::Post,
+                authorized("Factory reset", maintenance::handle_factory_reset),
+            )?;
+            // WiFi reconnect handler
+            server.tracked_handler(
+                "/reconnect",
+                Method::Post,
+                authorized("Reconnect", maintenance::handle_reconnect),
+            )?;
+            // Nearby access points handler
+            server.tracked_handler(
+                "/wifi/scan",
+                Method::Get,
+                authorized("WiFi scan", wifi_scan::handle),
+            )?;
+            // Operating mode handler
+            server.tracked_handler(
+                "/mode",
+                Method::Post,
+                authorized("Mode update", mode::handle_update),
+            )?;
+            // Log verbosity handlers
+            server.tracked_handler(
+                "/loglevel",
+                Method::Get,
+                |request| -> core::result::Result<(), EspIOError> {
+                    log_level::handle_get(request)
+                },
+            )?;
+            server.tracked_handler(
+                "/loglevel",
+                Method::Post,
+                authorized("Log level update", log_level::handle_update),
+            )?;
+            // Live gate status push
+            server.ws_handler("/ws", ws::handle)?;
+            ota::mark_running_firmware_valid();
+            // Prevent program from exiting, check WiFi connection every second
+            let mut idle_secs: u32 = 0;
+            loop {
+                if idle_secs % 60 == 0 {
+                    info!("Server awaiting connection");
+                }
+                FreeRtos::delay_ms(1000);
+                idle_secs = idle_secs.wrapping_add(1);
+                if let Some(watchdog) = watchdog.as_mut() {
+                    watchdog.feed()?;
+                }
+                if idle_secs % HEAP_CHECK_SECS == 0 {
+                    health::check_heap();
+                }
+                let reconnect_requested = maintenance::take_reconnect_request();
+                if reconnect_requested || !wifi.driver_mut().is_connected().unwrap() {
+                    if reconnect_requested {
+                        info!("Reconnecting WiFi on request");
+                    } else {
+                        info!("WiFi connection lost, reconnecting");
+                    }
+                    // Explicit teardown, so the server socket is closed and the port is free
+                    // before WiFi goes down and the next server is started
+                    ws::close_all();
+                    mqtt::stop();
+                    info!("Stopping HTTP server");
+                    drop(server);
+                    info!("Stopping mDNS responder");
+                    drop(mdns);
+                    drop(watchdog);
+                    info!("Stopping WiFi");
+                    drop(wifi);
+                    status_led::set_connected(false);
+                    info!("Network services stopped, reconnecting");
+                    break 'reconnect_loop;
+                }
+            }
+        }
+    }
+}
+// Main gate command request, see handle_gate_command()
+fn handle_command(
+    request: Request<&mut EspHttpConnection>,
+    name: &str,
+    action: Action,
+    command: fn() -> &'static str,
+) -> Result<(), EspIOError> {
+    handle_gate_command(request, name, action, command, gate_json_status)
+}
+// Gate command request: token and rate checks, relay action, reply in response_format.
+// status is the JSON status of the gate commanded, for the status format
+fn handle_gate_command(
+    mut request: Request<&mut EspHttpConnection>,
+    name: &str,
+    action: Action,
+    command: fn() -> &'static str,
+    status: fn() -> String,
+) -> Result<(), EspIOError> {
+    info!("Gate {} called", name);
+    if !is_authorized(&request) {
+        warn!("Gate {} rejected: wrong or missing token", name);
+        access_log::record(&mut request, action, 401);
+        return unauthorized(request);
+    }
+    if !allowlist::allowed(peer_ip(&mut request)) {
+        warn!("Gate {} rejected: address is not in allowed_ips", name);
+        access_log::record(&mut request, action, 403);
+        return allowlist::forbidden(request);
+    }
+    if action != Action::Close && !mode::opening_allowed() {
+        warn!("Gate {} rejected: gate is locked", name);
+        access_log::record(&mut request, action, 403);
+        return mode::forbidden(request);
+    }
+    if !rate_limit::try_accept() {
+        warn!("Gate {} rejected: previous command was too recent", name);
+        access_log::record(&mut request, action, 429);
+        return too_many_requests(request);
+    }
+    let reply = command();
+    let format = ResponseFormat::for_accept(request.header("Accept"));
+    let body = match format {
+        ResponseFormat::Compact => with_rssi(reply),
+        ResponseFormat::Status => status(),
+        ResponseFormat::Text => "OK".to_string(),
+    };
+    metrics::count_command(action);
+    access_log::record(&mut request, action, 200);
+    let mut headers = vec![("Content-Type", format.content_type())];
+    headers.extend_from_slice(cors::headers());
+    let mut response = request.into_response(200, Some("OK"), &headers)?;
+    response.write_all(body.as_bytes())?;
+    Ok(())
+}
+// Command reply with the server's own WiFi RSSI added as "rssi" under command_rssi, so the client
+// can log the signal at both ends: {"s":2} becomes {"s":2,"rssi":-61}
+fn with_rssi(reply: &str) -> String {
+    if !config().command_rssi {
+        return reply.to_string();
+    }
+    match (reply.strip_suffix('}'), current_rssi()) {
+        (Some(fields), Some(rssi)) => format!("{},\"rssi\":{}}}", fields, rssi),
+        _ => reply.to_string(),
+    }
+}
+// Gate command from JSON body like {"cmd":"open"}: open, sbs, close or macro (if configured)
+fn handle_json_command(mut request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let body = read_body(&mut request, 64)?;
+    let cmd = json_str_field(&body, "cmd");
+    let found = COMMANDS.into_iter().find(|(_, _, action, _)| {
+        Some(action.as_str()) == cmd && (*action != Action::Macro || gate_macro::enabled())
+    });
+    let Some((_, name, action, command)) = found else {
+        warn!("Gate command {:?} rejected: unknown command", body);
+        return json_error(
+            request,
+            400,
+            "unknown_command",
+            Some("cmd must be open, sbs, close or macro"),
+        );
+    };
+    handle_command(request, name, action, command)
+}
+// Gate command by GET, which link previews, prefetch and crawlers also send.
+// Without gate_token or basic_user anybody could operate the gate this way, so only POST is
+// allowed then
+fn handle_command_get(
+    request: Request<&mut EspHttpConnection>,
+    name: &str,
+    action: Action,
+    command: fn() -> &'static str,
+) -> Result<(), EspIOError> {
+    if !auth::auth_required() {
+        warn!("Gate {} by GET rejected: use POST", name);
+        return method_not_allowed(request);
+    }
+    handle_command(request, name, action, command)
+}
+// mDNS responder advertising HTTP service of the gate
+fn start_mdns(hostname: &str) -> anyhow::Result<EspMdns> {
+    let mut mdns = EspMdns::take()?;
+    mdns.set_hostname(hostname)?;
+    mdns.set_instance_name("Gate RTO-1000")?;
+    mdns.add_service(None, "_http", "_tcp", https::http_port(), &[])?;
+    info!("mDNS hostname {}.local registered", hostname);
+    Ok(mdns)
+}
+// Gate status as reported: moving for post_command_moving_ms after a relay pulse, as the sensors
+// lag the command while the gate starts, and until the other limit is reached within
+// expected_travel_ms, otherwise from the limit sensors
+fn gate_status() -> GateState {
+    if EspGateIo::MAIN.settling() {
+        info!("Gate moving after a relay pulse");
+        return GateState::Moving;
+    }
+    let status = sensor_status();
+    if travel::moving(status) {
+        info!("Gate moving after a command");
+        return GateState::Moving;
+    }
+    status
+}
+// Gate status from the limit sensors
+fn sensor_status() -> GateState {
+    let status = gate_io::read_status(
+        &EspGateIo::MAIN,
+        config().sensor_samples,
+        config().sensors_active_low,
+    );
+    match status {
+        GateState::Open => info!("Gate opened"),
+        GateState::Closed => info!("Gate closed"),
+        GateState::Moving => info!("Gate in middle position"),
+        GateState::Fault => {
+            error!("Both gate limit sensors are triggered, sensor or wiring failed")
+        }
+    }
+    status
+}
+// Gate status in JSON, see StatusReport
+fn gate_json_status() -> String {
+    let schedule = schedule::json();
+    let remote = remote::json();
+    let status = gate_status();
+    let stalled = status == GateState::Moving && travel::stalled();
+    let (status, error) = reported(status, stalled, travel::error());
+    StatusReport {
+        status,
+        moving_until: travel::remaining_ms(),
+        opened: EspGateIo::MAIN.opened_high(),
+        closed: EspGateIo::MAIN.closed_high(),
+        rssi: current_rssi(),
+        ipv6: &ipv6::addresses(),
+        uptime: START_TIME.elapsed().as_secs(),
+        version: env!("CARGO_PKG_VERSION"),
+        error,
+        schedule: &schedule,
+        mode: mode::current().as_str(),
+        remote: &remote,
+    }
+    .json()
+}
+// Gate step-by-step (SBS) command handler.
+// SBS within sbs_cooldown_ms after the previous pulse is ignored, as the controller may take it
+// for a direction change in progress, current status is returned instead
+fn gate_sbs() -> &'static str {
+    if gate_status() == GateState::Fault {
+        warn!("Gate SBS refused: limit sensor fault");
+        return status_reply(GateState::Fault);
+    }
+    let cooldown = Duration::from_millis(config().sbs_cooldown_ms as u64);
+    let last_pulse = *LAST_SBS_PULSE.clone().lock();
+    if last_pulse.is_some_and(|last_pulse| last_pulse.elapsed() < cooldown) {
+        info!(
+            "Gate SBS ignored: previous SBS pulse was less than {:?} ago",
+            cooldown
+        );
+        return status_reply(gate_status());
+    }
+    let was_closed = gate_status() == GateState::Closed;
+    auto_close::cancel();
+    pulse_sbs();
+    if was_closed {
+        auto_close::arm();
+    }
+    "{\"s\":2}"
+}
+// Command reply with the gate status
+fn status_reply(status: GateState) -> &'static str {
+    match status {
+        GateState::Open => "{\"s\":0}",
+        GateState::Closed => "{\"s\":1}",
+        GateState::Moving => "{\"s\":2}",
+        GateState::Fault => "{\"s\":3}",
+    }
+}
+// Gate step-by-step (SBS) relay pulse
+fn pulse_sbs() {
+    CommandedGate.pulse_sbs(settings::current().sbs_pulse_ms);
+}
+// Main gate as commands drive it: an SBS pulse is warned by the buzzer, starts the SBS cooldown
+// and the travel timeout and counts as an action for health
+struct CommandedGate;
+impl GateIo for CommandedGate {
+    fn opened_high(&self) -> bool {
+        EspGateIo::MAIN.opened_high()
+    }
+    fn closed_high(&self) -> bool {
+        EspGateIo::MAIN.closed_high()
+    }
+    fn pulse_open(&self, ms: u32) {
+        EspGateIo::MAIN.pulse_open(ms);
+    }
+    fn pulse_sbs(&self, ms: u32) {
+        buzzer::warn_before_motion();
+        EspGateIo::MAIN.pulse_sbs(ms);
+        *LAST_SBS_PULSE.clone().lock() = Some(Instant::now());
+        health::record_action();
+        travel::start();
+    }
+    fn pause_ms(&self, ms: u32) {
+        EspGateIo::MAIN.pause_ms(ms);
+    }
+}
+// Gate open command handler, auto-close is armed after the pulse
+// Relay is not pulsed when the gate is already opened, {"s":0} tells the client so
+fn gate_open() -> &'static str {
+    open_gate(true)
+}
+// Open the gate, arming auto-close after the pulse with auto_close. A scheduled open does not arm
+// it: the gate stays open until the scheduled close
+fn open_gate(auto_close: bool) -> &'static str {
+    match gate_status() {
+        GateState::Open => {
+            info!("Gate already opened");
+            return status_reply(GateState::Open);
+        }
+        GateState::Fault => {
+            warn!("Gate open refused: limit sensor fault");
+            return status_reply(GateState::Fault);
+        }
+        GateState::Closed | GateState::Moving => {}
+    }
+    buzzer::warn_before_motion();
+    EspGateIo::MAIN.pulse_open(settings::current().open_pulse_ms);
+    health::record_action();
+    travel::start();
+    if auto_close {
+        auto_close::arm();
+    }
+    "{\"s\":2}"
+}
+// Gate close command handler, see gate_io::close
+fn gate_close() -> &'static str {
+    let status = gate_status();
+    match status {
+        GateState::Open => auto_close::cancel(),
+        GateState::Closed => info!("Gate already closed"),
+        GateState::Moving => info!("Gate in middle position, close ignored"),
+        GateState::Fault => warn!("Gate close refused: limit sensor fault"),
+    }
+    let sbs_pulse_ms = settings::current().sbs_pulse_ms;
+    status_reply(gate_io::close(&CommandedGate, status, sbs_pulse_ms))
+}
+// Open and SBS commands of the gate with 1-based id.
+// Auto-close, travel timeout, SBS cooldown and macro are of the main gate only
+fn gate_commands(id: usize) -> [(&'static str, Action, fn() -> &'static str); 2] {
+    if id == 1 {
+        [
+            ("open", Action::Open, gate_open),
+            ("sbs", Action::Sbs, gate_sbs),
+        ]
+    } else {
+        [
+            ("open", Action::Open, gate2_open),
+            ("sbs", Action::Sbs, gate2_sbs),
+        ]
+    }
+}
+// Second gate status from its limit sensors, moving for post_command_moving_ms after a pulse
+fn gate2_status() -> GateState {
+    if EspGateIo::SECOND.settling() {
+        return GateState::Moving;
+    }
+    gate_io::read_status(
+        &EspGateIo::SECOND,
+        config().sensor_samples,
+        config().sensors_active_low,
+    )
+}
+// Second gate status in JSON: s - gate status, raw sensor levels
+fn gate2_json_status() -> String {
+    format!(
+        "{{\"s\":{},\"opened\":{},\"closed\":{}}}",
+        gate2_status().to_u8(),
+        EspGateIo::SECOND.opened_high(),
+        EspGateIo::SECOND.closed_high()
+    )
+}
+// Second gate open command handler, the relay is not pulsed when the gate is already opened
+fn gate2_open() -> &'static str {
+    match gate2_status() {
+        GateState::Open => {
+            info!("Gate 2 already opened");
+            return status_reply(GateState::Open);
+        }
+        GateState::Fault => {
+            warn!("Gate 2 open refused: limit sensor fault");
+            return status_reply(GateState::Fault);
+        }
+        GateState::Closed | GateState::Moving => {}
+    }
+    buzzer::warn_before_motion();
+    EspGateIo::SECOND.pulse_open(settings::current().open_pulse_ms);
+    health::record_action();
+    "{\"s\":2}"
+}
+// Second gate step-by-step (SBS) command handler
+fn gate2_sbs() -> &'static str {
+    if gate2_status() == GateState::Fault {
+        warn!("Gate 2 SBS refused: limit sensor fault");
+        return status_reply(GateState::Fault);
+    }
+    buzzer::warn_before_motion();
+    EspGateIo::SECOND.pulse_sbs(settings::current().sbs_pulse_ms);
+    health::record_action();
+    "{\"s\":2}"
+}
+// Gate main page constructor, the second gate is shown below the main one if configured
+fn gate_page() -> String {
+    let mut html = String::from(include_str!("index-0.html"));
+    html.push_str(&gate_controls("", gate_status()));
+    if hardware().gates.len() > 1 {
+        html.push_str("<h1>Ворота 2</h1>");
+        html.push_str(&gate_controls("2", gate2_status()));
+    }
+    html.push_str(include_str!("index-1.html"));
+    html
+}
+// Status and button of a gate, element ids end with suffix, see gates in index-1.html
+fn gate_controls(suffix: &str, status: GateState) -> String {
+    let (label, button, attribute) = match status {
+        GateState::Open => ("Открыто", "Закрыть", "autofocus"),
+        GateState::Closed => ("Закрыто", "Открыть", "autofocus"),
+        GateState::Moving => (
+            "Промежуточное положение",
+            "Открыть/Закрыть/Стоп",
+            "disabled",
+        ),
+        GateState::Fault => ("Неисправность датчиков", "Открыть/Закрыть/Стоп", "disabled"),
+    };
+    format!(
+        "<h2><div id=\"status{0}\">{1}</div></h2><button id=\"sbs_button{0}\" class=\"button\" onclick=\"sbs_gate('{0}')\" {3}>{2}</button>",
+        suffix, label, button, attribute
+    )
+}
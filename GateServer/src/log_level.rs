@@ -0,0 +1,74 @@
+// Log verbosity set by log_level at startup and changed live by POST /loglevel?level=debug,
+// so an intermittent problem can be chased without reflashing. The level applies to all log
+// targets, ESP-IDF components included. debug is the most verbose level compiled in
+// (CONFIG_LOG_MAXIMUM_LEVEL_DEBUG), a live change is lost on reboot.
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use lazy_static::lazy_static;
+use log::{error, info, warn, LevelFilter};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use crate::web::{form_field, json_error, read_body};
+use crate::{auth::query_param, config, cors};
+
+// Level names accepted by log_level and /loglevel
+pub const LEVELS: &[&str] = &["off", "error", "warn", "info", "debug"];
+
+lazy_static! {
+    static ref LEVEL: Arc<Mutex<LevelFilter>> = Arc::new(Mutex::new(LevelFilter::Info));
+}
+
+// Level by its name, None - not one of LEVELS
+pub fn parse(name: &str) -> Option<LevelFilter> {
+    if !LEVELS.contains(&name.trim().to_ascii_lowercase().as_str()) {
+        return None;
+    }
+    name.trim().parse().ok()
+}
+
+// Apply the validated log_level
+pub fn init() {
+    apply(parse(config().log_level).unwrap_or(LevelFilter::Info));
+}
+
+fn apply(level: LevelFilter) {
+    if let Err(e) = esp_idf_svc::log::set_target_level("*", level) {
+        error!("Can not set log level {}: {}", level, e);
+        return;
+    }
+    *LEVEL.clone().lock() = level;
+}
+
+// Current level in JSON like {"level":"info"}
+fn json() -> String {
+    format!(
+        "{{\"level\":\"{}\"}}",
+        LEVEL.clone().lock().as_str().to_ascii_lowercase()
+    )
+}
+
+// Level from the level query parameter or form field, replies with the current level in JSON
+pub fn handle_update(mut request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let level = match query_param(request.uri(), "level") {
+        Some(level) => Some(level),
+        None => form_field(&read_body(&mut request, 64)?, "level"),
+    };
+    let Some(level) = level.as_deref().and_then(parse) else {
+        warn!("Log level update rejected: {:?}", level);
+        let message = format!("level must be one of {}", LEVELS.join(", "));
+        return json_error(request, 400, "bad_request", Some(&message));
+    };
+    apply(level);
+    info!("Log level set to {}", level);
+    let mut response = request.into_response(200, Some("OK"), cors::headers())?;
+    response.write_all(json().as_bytes())?;
+    Ok(())
+}
+
+// Current level, no token needed
+pub fn handle_get(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let mut response = request.into_response(200, Some("OK"), cors::headers())?;
+    response.write_all(json().as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_hal::{delay::FreeRtos, reset};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use log::{error, info};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::settings;
+use crate::web::{json_error, json_string};
+
+// Set by /reconnect, taken by the main loop
+static RECONNECT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// Reboot after the response has been sent
+pub fn handle_restart(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    info!("Restart requested. Rebooting");
+    let mut response = request.into_ok_response()?;
+    response.write_all(b"Rebooting\n")?;
+    std::thread::spawn(|| {
+        FreeRtos::delay_ms(1000);
+        reset::restart();
+    });
+    Ok(())
+}
+
+// Erase settings stored in NVS and reboot with the compiled ones,
+// replies with the erased keys like {"erased":["wifi_ssid","mode"]}
+pub fn handle_factory_reset(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let erased = match settings::erase() {
+        Ok(erased) => erased,
+        Err(e) => {
+            error!("Factory reset failed: {}", e);
+            return json_error(request, 500, "nvs_error", Some("Can not erase settings"));
+        }
+    };
+    if erased.is_empty() {
+        info!("Factory reset: no settings stored in NVS. Rebooting");
+    } else {
+        info!(
+            "Factory reset: {} erased from NVS. Rebooting",
+            erased.join(", ")
+        );
+    }
+    let keys = erased
+        .iter()
+        .map(|key| json_string(key))
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut response = request.into_ok_response()?;
+    response.write_all(format!("{{\"erased\":[{}]}}", keys).as_bytes())?;
+    std::thread::spawn(|| {
+        FreeRtos::delay_ms(1000);
+        reset::restart();
+    });
+    Ok(())
+}
+
+// Drop WiFi and connect again, the main loop leaves the reconnect block within a second
+pub fn handle_reconnect(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    info!("WiFi reconnect requested");
+    let mut response = request.into_ok_response()?;
+    response.write_all(b"Reconnecting WiFi\n")?;
+    RECONNECT_REQUESTED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+// True once after /reconnect was called
+pub fn take_reconnect_request() -> bool {
+    RECONNECT_REQUESTED.swap(false, Ordering::Relaxed)
+}
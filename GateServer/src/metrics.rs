@@ -0,0 +1,82 @@
+use esp_idf_svc::sys::{heap_caps_get_free_size, MALLOC_CAP_8BIT};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::access_log::Action;
+use crate::{gate_status, wifi::current_rssi, START_TIME};
+
+// Executed gate commands since start, by HTTP command handlers
+static OPEN_COMMANDS: AtomicU32 = AtomicU32::new(0);
+static SBS_COMMANDS: AtomicU32 = AtomicU32::new(0);
+static CLOSE_COMMANDS: AtomicU32 = AtomicU32::new(0);
+static MACRO_COMMANDS: AtomicU32 = AtomicU32::new(0);
+
+// Count an executed command
+pub fn count_command(action: Action) {
+    let counter = match action {
+        Action::Open => &OPEN_COMMANDS,
+        Action::Sbs => &SBS_COMMANDS,
+        Action::Close => &CLOSE_COMMANDS,
+        Action::Macro => &MACRO_COMMANDS,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+// Metrics in Prometheus text exposition format
+pub fn text() -> String {
+    let mut text = String::new();
+    metric(
+        &mut text,
+        "gate_state",
+        "gauge",
+        "Gate position: 0 - open, 1 - closed, 2 - moving, 3 - sensor fault",
+        gate_status().to_u8(),
+    );
+    // No sample while WiFi is not connected
+    if let Some(rssi) = current_rssi() {
+        metric(
+            &mut text,
+            "wifi_rssi",
+            "gauge",
+            "WiFi signal strength in dBm",
+            rssi,
+        );
+    }
+    metric(
+        &mut text,
+        "free_heap_bytes",
+        "gauge",
+        "Free heap in bytes",
+        unsafe { heap_caps_get_free_size(MALLOC_CAP_8BIT) },
+    );
+    metric(
+        &mut text,
+        "uptime_seconds",
+        "counter",
+        "Seconds since start",
+        START_TIME.elapsed().as_secs(),
+    );
+    text.push_str("# HELP gate_commands_total Executed gate commands since start\n");
+    text.push_str("# TYPE gate_commands_total counter\n");
+    for (command, counter) in [
+        ("open", &OPEN_COMMANDS),
+        ("sbs", &SBS_COMMANDS),
+        ("close", &CLOSE_COMMANDS),
+        ("macro", &MACRO_COMMANDS),
+    ] {
+        let _ = writeln!(
+            text,
+            "gate_commands_total{{command=\"{}\"}} {}",
+            command,
+            counter.load(Ordering::Relaxed)
+        );
+    }
+    text
+}
+
+// Single sample metric with its HELP and TYPE lines
+fn metric(text: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+    let _ = writeln!(text, "{} {}", name, value);
+}
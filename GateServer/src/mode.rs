@@ -0,0 +1,122 @@
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use crate::storage::{self, Namespace};
+use crate::web::{form_field, json_error, read_body};
+use crate::{auto_close, cors};
+
+// Operating mode, persisted in NVS
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Normal,
+    // Gate stays open: auto-close is suspended
+    HoldOpen,
+    // Open and SBS commands from HTTP, MQTT and schedule are refused
+    Locked,
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::HoldOpen => "hold_open",
+            Mode::Locked => "locked",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Mode> {
+        match value {
+            "normal" => Some(Mode::Normal),
+            "hold_open" => Some(Mode::HoldOpen),
+            "locked" => Some(Mode::Locked),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(value: u8) -> Option<Mode> {
+        match value {
+            0 => Some(Mode::Normal),
+            1 => Some(Mode::HoldOpen),
+            2 => Some(Mode::Locked),
+            _ => None,
+        }
+    }
+}
+
+lazy_static! {
+    /// Current operating mode
+    static ref MODE: Arc<Mutex<Mode>> = Arc::new(Mutex::new(load()));
+}
+
+fn load() -> Mode {
+    // Stored in the settings namespace, erased with the settings by factory reset
+    let stored = storage::open(Namespace::Settings).map(|nvs| nvs.get_u8("mode"));
+    match stored {
+        Ok(Some(value)) => match Mode::from_u8(value) {
+            Some(mode) => {
+                info!("Mode {} loaded from NVS", mode.as_str());
+                mode
+            }
+            None => {
+                warn!("Unknown mode {} in NVS, using normal", value);
+                Mode::Normal
+            }
+        },
+        Ok(None) => Mode::Normal,
+        Err(e) => {
+            error!("Can not read mode from NVS, using normal: {}", e);
+            Mode::Normal
+        }
+    }
+}
+
+// Current operating mode
+pub fn current() -> Mode {
+    *MODE.clone().lock()
+}
+
+// Open and SBS commands are accepted, i.e. the gate is not locked
+pub fn opening_allowed() -> bool {
+    current() != Mode::Locked
+}
+
+// 403 response for commands refused in locked mode
+pub fn forbidden(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    json_error(request, 403, "locked", Some("Gate is locked"))
+}
+
+// Mode change from form field mode, replies with the current mode in JSON
+pub fn handle_update(mut request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let body = read_body(&mut request, 64)?;
+    let Some(mode) = form_field(&body, "mode").as_deref().and_then(Mode::parse) else {
+        warn!("Mode update rejected: {:?}", body);
+        return json_error(
+            request,
+            400,
+            "bad_request",
+            Some("mode must be normal, hold_open or locked"),
+        );
+    };
+    if let Err(e) =
+        storage::open(Namespace::Settings).and_then(|mut nvs| nvs.set_u8("mode", mode.to_u8()))
+    {
+        // Mode is applied anyway, it is only lost on reboot
+        error!("Can not save mode to NVS: {}", e);
+    }
+    *MODE.clone().lock() = mode;
+    info!("Mode set to {}", mode.as_str());
+    if mode == Mode::HoldOpen {
+        auto_close::cancel();
+    }
+    let mut response = request.into_response(200, Some("OK"), cors::headers())?;
+    response.write_all(format!("{{\"mode\":\"{}\"}}", mode.as_str()).as_bytes())?;
+    Ok(())
+}
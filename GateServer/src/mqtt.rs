@@ -0,0 +1,127 @@
+use embedded_svc::mqtt::client::{EventPayload, QoS};
+use esp_idf_svc::mqtt::client::{EspMqttClient, MqttClientConfiguration};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::sync::{
+    mpsc::{self, Sender},
+    Arc,
+};
+
+use crate::{config, gate_open, gate_sbs, gate_state::GateState, gate_status, mode};
+
+// Requests to the client task
+enum Message {
+    Connected,
+    Status(GateState),
+}
+
+lazy_static! {
+    /// Channel to the client task, lives while WiFi is connected
+    static ref MQTT_TASK: Arc<Mutex<Option<Sender<Message>>>> = Arc::new(Mutex::new(None));
+}
+
+// Connect to the broker, if mqtt_url is configured.
+// Client reconnects to the broker by itself, WiFi reconnect needs stop() and start().
+// The client is owned by its own task, so publishing never waits for MQTT event handling.
+pub fn start() -> anyhow::Result<()> {
+    if config().mqtt_url.is_empty() {
+        return Ok(());
+    }
+    info!("Connecting MQTT broker {}", config().mqtt_url);
+    let (mut client, mut connection) = EspMqttClient::new(
+        config().mqtt_url,
+        &MqttClientConfiguration {
+            client_id: Some(config().mdns_hostname),
+            username: (!config().mqtt_user.is_empty()).then_some(config().mqtt_user),
+            password: (!config().mqtt_pass.is_empty()).then_some(config().mqtt_pass),
+            ..Default::default()
+        },
+    )?;
+    let (sender, receiver) = mpsc::channel();
+    *MQTT_TASK.clone().lock() = Some(sender);
+    std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(move || {
+            // Ends when stop() drops the sender, the client is dropped with the task
+            while let Ok(message) = receiver.recv() {
+                match message {
+                    Message::Connected => {
+                        let topic = format!("{}/set", config().mqtt_topic);
+                        if let Err(e) = client.subscribe(&topic, QoS::AtLeastOnce) {
+                            error!("MQTT subscribe to {} failed: {}", topic, e);
+                        }
+                        publish(&mut client, gate_status());
+                    }
+                    Message::Status(status) => publish(&mut client, status),
+                }
+            }
+        })?;
+    std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(move || {
+            while let Ok(event) = connection.next() {
+                match event.payload() {
+                    EventPayload::Connected(_) => {
+                        info!("MQTT connected");
+                        send(Message::Connected);
+                    }
+                    EventPayload::Disconnected => info!("MQTT disconnected"),
+                    EventPayload::Received { data, .. } => command(data),
+                    EventPayload::Error(e) => warn!("MQTT error: {}", e),
+                    _ => {}
+                }
+            }
+            info!("MQTT connection closed");
+        })?;
+    Ok(())
+}
+
+// Stop the client, called before WiFi reconnect.
+// The client task ends and drops the client, then the connection task logs it is closed
+pub fn stop() {
+    if MQTT_TASK.clone().lock().take().is_some() {
+        info!("Stopping MQTT client");
+    }
+}
+
+// Publish gate state, if MQTT is connected
+pub fn publish_status(status: GateState) {
+    send(Message::Status(status));
+}
+
+fn send(message: Message) {
+    if let Some(sender) = MQTT_TASK.clone().lock().as_ref() {
+        // Task has ended only if the client failed, nothing to do then
+        let _ = sender.send(message);
+    }
+}
+
+// Retained gate state open/closed/moving to <mqtt_topic>/state
+fn publish(client: &mut EspMqttClient<'static>, status: GateState) {
+    let state = status.to_string();
+    let topic = format!("{}/state", config().mqtt_topic);
+    match client.publish(&topic, QoS::AtLeastOnce, true, state.as_bytes()) {
+        Ok(_) => info!("MQTT {} published to {}", state, topic),
+        Err(e) => error!("MQTT publish to {} failed: {}", topic, e),
+    }
+}
+
+// Command from <mqtt_topic>/set
+fn command(data: &[u8]) {
+    if !mode::opening_allowed() {
+        warn!("MQTT command ignored: gate is locked");
+        return;
+    }
+    match std::str::from_utf8(data).map(str::trim) {
+        Ok("open") => {
+            info!("MQTT open command");
+            gate_open();
+        }
+        Ok("sbs") => {
+            info!("MQTT SBS command");
+            gate_sbs();
+        }
+        _ => warn!("Unknown MQTT command {:?}", String::from_utf8_lossy(data)),
+    }
+}
@@ -0,0 +1,63 @@
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_hal::{delay::FreeRtos, reset};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection, ota::EspOta};
+use log::{error, info, warn};
+
+use crate::web::json_error;
+
+// Firmware update from POST body, reboot into the new firmware on success
+pub fn handle_update(mut request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    match write_firmware(&mut request) {
+        Ok(size) => {
+            info!("Firmware update written, {} bytes. Rebooting", size);
+            let mut response = request.into_ok_response()?;
+            response.write_all(
+                format!("Firmware updated, {} bytes written. Rebooting\n", size).as_bytes(),
+            )?;
+            // Reboot after the response has been sent
+            std::thread::spawn(|| {
+                FreeRtos::delay_ms(1000);
+                reset::restart();
+            });
+        }
+        Err(e) => {
+            error!("Firmware update failed: {}", e);
+            let message = format!("Firmware update failed: {}", e);
+            json_error(request, 500, "update_failed", Some(&message))?;
+        }
+    }
+    Ok(())
+}
+
+// Write request body to the inactive OTA partition.
+// On any error the update is dropped, which aborts it and keeps the running
+// partition as boot partition.
+fn write_firmware(request: &mut Request<&mut EspHttpConnection>) -> anyhow::Result<usize> {
+    let mut ota = EspOta::new()?;
+    let mut update = ota.initiate_update()?;
+    let mut buf = vec![0u8; 1024];
+    let mut size = 0;
+    loop {
+        let bytes_read = request.read(&mut buf)?;
+        if bytes_read == 0 {
+            break;
+        }
+        update.write(&buf[..bytes_read])?;
+        // Progress every 64 KB
+        if (size + bytes_read) / 0x10000 != size / 0x10000 {
+            info!("Firmware update: {} bytes written", size + bytes_read);
+        }
+        size += bytes_read;
+    }
+    // Image is verified here, boot partition is switched only if it is valid
+    update.complete()?;
+    Ok(size)
+}
+
+// Confirm the running firmware, so the bootloader does not roll it back
+pub fn mark_running_firmware_valid() {
+    match EspOta::new().and_then(|mut ota| ota.mark_running_slot_valid()) {
+        Ok(()) => info!("Running firmware marked valid"),
+        Err(e) => warn!("Can not mark running firmware valid: {}", e),
+    }
+}
@@ -0,0 +1,36 @@
+use embedded_svc::http::server::Request;
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{config, web::json_error};
+
+lazy_static! {
+    /// Time of the last accepted command request
+    static ref LAST_COMMAND: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
+// Accept a command request if min_command_interval_ms has passed since the previous
+// accepted one, so a command storm can not pulse the relay rapidly
+pub fn try_accept() -> bool {
+    let min_interval = Duration::from_millis(config().min_command_interval_ms as u64);
+    let last_command = LAST_COMMAND.clone();
+    let mut last_command = last_command.lock();
+    let now = Instant::now();
+    if let Some(last) = *last_command {
+        if now.duration_since(last) < min_interval {
+            return false;
+        }
+    }
+    *last_command = Some(now);
+    true
+}
+
+// 429 response for command requests arriving too fast
+pub fn too_many_requests(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    json_error(request, 429, "rate_limited", None)
+}
@@ -0,0 +1,49 @@
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use lazy_static::lazy_static;
+use log::info;
+use parking_lot::Mutex;
+use std::{sync::Arc, time::Instant};
+
+use crate::{auth::query_param, cors};
+
+// Last heartbeat of the GateControl unit: when it was received and the RSSI it reported
+#[derive(Clone, Copy)]
+struct Ping {
+    time: Instant,
+    rssi: Option<i8>,
+}
+
+lazy_static! {
+    static ref LAST_PING: Arc<Mutex<Option<Ping>>> = Arc::new(Mutex::new(None));
+}
+
+// GET /ping?rssi=N from GateControl while it is connected, rssi is optional
+pub fn handle_ping(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let rssi = query_param(request.uri(), "rssi").and_then(|rssi| rssi.parse::<i8>().ok());
+    *LAST_PING.clone().lock() = Some(Ping {
+        time: Instant::now(),
+        rssi,
+    });
+    match rssi {
+        Some(rssi) => info!("Ping from GateControl with rssi {}", rssi),
+        None => info!("Ping from GateControl"),
+    }
+    let mut response = request.into_response(200, Some("OK"), cors::headers())?;
+    response.write_all(b"{}")?;
+    Ok(())
+}
+
+// GateControl presence in JSON: seen_secs_ago - seconds since the last ping, rssi - reported
+// signal strength, null - no ping since start
+pub fn json() -> String {
+    match *LAST_PING.clone().lock() {
+        Some(ping) => format!(
+            "{{\"seen_secs_ago\":{},\"rssi\":{}}}",
+            ping.time.elapsed().as_secs(),
+            ping.rssi
+                .map_or("null".to_string(), |rssi| rssi.to_string())
+        ),
+        None => "null".to_string(),
+    }
+}
@@ -0,0 +1,49 @@
+// Body of the gate command replies by response_format, for integrations expecting another shape:
+// compact - gate position like {"s":2}, status - the full /gate_status JSON after the command,
+// text - plain OK. A client asking for JSON by Accept, as GateControl does, gets compact instead
+// of text, so it still sees the gate status. Errors are JSON in all formats.
+use crate::config;
+
+// Format names accepted by response_format
+pub const FORMATS: &[&str] = &["compact", "status", "text"];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ResponseFormat {
+    Compact,
+    Status,
+    Text,
+}
+
+impl ResponseFormat {
+    pub fn parse(name: &str) -> Option<ResponseFormat> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "compact" => Some(ResponseFormat::Compact),
+            "status" => Some(ResponseFormat::Status),
+            "text" => Some(ResponseFormat::Text),
+            _ => None,
+        }
+    }
+
+    // response_format, an unknown one is replaced by compact in validation
+    pub fn current() -> ResponseFormat {
+        Self::parse(config().response_format).unwrap_or(ResponseFormat::Compact)
+    }
+
+    // response_format for a request with this Accept header
+    pub fn for_accept(accept: Option<&str>) -> ResponseFormat {
+        let format = Self::current();
+        if format == ResponseFormat::Text
+            && accept.is_some_and(|accept| accept.contains("application/json"))
+        {
+            return ResponseFormat::Compact;
+        }
+        format
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ResponseFormat::Compact | ResponseFormat::Status => "application/json",
+            ResponseFormat::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
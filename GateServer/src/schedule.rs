@@ -0,0 +1,182 @@
+use esp_idf_hal::delay::FreeRtos;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::mode;
+use crate::web::json_string;
+use crate::{auto_close, config, gate_close, gate_state::GateState, gate_status, open_gate};
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+// Clock earlier than 2023-11-14 means SNTP has not synced yet
+const MIN_VALID_UNIX_SECS: u64 = 1_700_000_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Event {
+    Open,
+    Close,
+}
+
+impl Event {
+    fn as_str(self) -> &'static str {
+        match self {
+            Event::Open => "open",
+            Event::Close => "close",
+        }
+    }
+}
+
+lazy_static! {
+    /// Scheduled events with local minute of day, parsed from schedule_open and schedule_close
+    static ref EVENTS: Vec<(Event, i64)> = parse_events();
+}
+
+fn parse_events() -> Vec<(Event, i64)> {
+    let mut events = Vec::new();
+    for (event, time) in [
+        (Event::Open, config().schedule_open),
+        (Event::Close, config().schedule_close),
+    ] {
+        if time.is_empty() {
+            continue;
+        }
+        match parse_time(time) {
+            Some(minute) => events.push((event, minute)),
+            None => warn!("Schedule time {:?} is not HH:MM, ignored", time),
+        }
+    }
+    events
+}
+
+// Minute of day from HH:MM
+fn parse_time(time: &str) -> Option<i64> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = minutes.parse().ok()?;
+    ((0..24).contains(&hours) && (0..60).contains(&minutes)).then_some(hours * 60 + minutes)
+}
+
+// At least one valid schedule time is configured
+pub fn enabled() -> bool {
+    !EVENTS.is_empty()
+}
+
+// Schedule task, checks minute boundaries every 20 seconds
+pub fn spawn_task() -> anyhow::Result<()> {
+    std::thread::Builder::new().stack_size(8192).spawn(|| {
+        let mut last_minute = None;
+        loop {
+            FreeRtos::delay_ms(20_000);
+            last_minute = check(last_minute);
+        }
+    })?;
+    Ok(())
+}
+
+// Local time in minutes since epoch, None until the clock is synced
+fn local_minute() -> Option<i64> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    if secs < MIN_VALID_UNIX_SECS {
+        return None;
+    }
+    Some((secs as i64 + config().tz_offset_minutes as i64 * 60).div_euclid(60))
+}
+
+// ISO day of week of the local minute, 1 - Monday .. 7 - Sunday
+fn weekday(minute: i64) -> u32 {
+    // 1970-01-01 was Thursday
+    ((minute.div_euclid(MINUTES_PER_DAY) + 3).rem_euclid(7) + 1) as u32
+}
+
+fn day_enabled(minute: i64) -> bool {
+    let day = char::from_digit(weekday(minute), 10).unwrap_or('0');
+    config().schedule_days.contains(day)
+}
+
+// Scheduled event at the local minute, if any
+fn event_at(minute: i64) -> Option<Event> {
+    if !day_enabled(minute) {
+        return None;
+    }
+    let minute_of_day = minute.rem_euclid(MINUTES_PER_DAY);
+    EVENTS
+        .iter()
+        .find(|(_, event_minute)| *event_minute == minute_of_day)
+        .map(|(event, _)| *event)
+}
+
+// Fire events whose minute was crossed since the previous check
+fn check(last_minute: Option<i64>) -> Option<i64> {
+    let now = local_minute()?;
+    let Some(last) = last_minute else {
+        return Some(now);
+    };
+    // Clock set back or jumped far ahead, e.g. first SNTP sync: do not replay missed events
+    if now <= last || now - last > MINUTES_PER_DAY {
+        return Some(now);
+    }
+    for minute in last + 1..=now {
+        if let Some(event) = event_at(minute) {
+            fire(event);
+        }
+    }
+    Some(now)
+}
+
+// Issue the scheduled command unless the gate is already in the desired state
+fn fire(event: Event) {
+    let status = gate_status();
+    match (event, status) {
+        (Event::Open, GateState::Open) => {
+            info!("Scheduled open: gate already {}", status);
+            // Opened by a command shortly before, it stays open until the scheduled close
+            auto_close::cancel();
+        }
+        (Event::Close, GateState::Closed) => {
+            info!("Scheduled close: gate already {}", status);
+        }
+        (Event::Open, _) if !mode::opening_allowed() => {
+            info!("Scheduled open skipped: gate is locked");
+        }
+        (Event::Open, _) => {
+            info!("Scheduled open");
+            open_gate(false);
+        }
+        (Event::Close, _) => {
+            info!("Scheduled close");
+            gate_close();
+        }
+    }
+}
+
+// Schedule in JSON for gate status, null - no schedule.
+// next/next_in - next event and seconds until it, null - clock not synced yet
+pub fn json() -> String {
+    if !enabled() {
+        return "null".to_string();
+    }
+    let next = local_minute().and_then(|now| {
+        (1..=8 * MINUTES_PER_DAY)
+            .find_map(|ahead| event_at(now + ahead).map(|event| (event, ahead)))
+    });
+    let (next, next_in) = match next {
+        Some((event, ahead)) => {
+            let secs_into_minute = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs() % 60) as i64;
+            (
+                format!("\"{}\"", event.as_str()),
+                (ahead * 60 - secs_into_minute).to_string(),
+            )
+        }
+        None => ("null".to_string(), "null".to_string()),
+    };
+    format!(
+        "{{\"open\":{},\"close\":{},\"days\":{},\"next\":{},\"next_in\":{}}}",
+        json_string(config().schedule_open),
+        json_string(config().schedule_close),
+        json_string(config().schedule_days),
+        next,
+        next_in
+    )
+}
@@ -0,0 +1,97 @@
+use esp_idf_hal::{
+    delay::{FreeRtos, TickType},
+    gpio::InterruptType,
+    task::notification::Notification,
+};
+use log::{error, info};
+use std::{num::NonZeroU32, time::Duration};
+
+use crate::gate_io::EspGateIo;
+use crate::{config, gate_status, hardware, mqtt, telegram, ws};
+
+// Sensor levels settle after an edge before the status is read
+const SETTLE_MS: u32 = 50;
+// Status is also checked this often, in case an edge was missed
+const FALLBACK_CHECK_MS: u64 = 5000;
+// After a relay pulse the reported status also changes without an edge, once
+// post_command_moving_ms is over, so it is checked this often until FAST_CHECK after that
+const FAST_CHECK_MS: u64 = 200;
+const FAST_CHECK: Duration = Duration::from_secs(5);
+
+// Sensor watcher task, lives outside the WiFi reconnect loop.
+// Only the main gate is watched, the second gate status is not pushed to listeners
+pub fn spawn_task() -> anyhow::Result<()> {
+    std::thread::Builder::new().stack_size(8192).spawn(|| {
+        if let Err(e) = watch() {
+            error!("Sensor watcher stopped: {}", e);
+        }
+    })?;
+    Ok(())
+}
+
+// GPIO interrupts on sensor edges only notify this task,
+// sensors are read and listeners are informed here
+fn watch() -> anyhow::Result<()> {
+    // Notification is bound to the task it is created in
+    let notification = Notification::new();
+    {
+        let notifier = notification.notifier();
+        let gate_opened = hardware().gates[0].opened.clone();
+        let mut gate_opened = gate_opened.lock();
+        gate_opened.set_interrupt_type(InterruptType::AnyEdge)?;
+        unsafe {
+            gate_opened.subscribe(move || {
+                notifier.notify_and_yield(NonZeroU32::new(1).unwrap());
+            })?;
+        }
+        gate_opened.enable_interrupt()?;
+    }
+    {
+        let notifier = notification.notifier();
+        let gate_closed = hardware().gates[0].closed.clone();
+        let mut gate_closed = gate_closed.lock();
+        gate_closed.set_interrupt_type(InterruptType::AnyEdge)?;
+        unsafe {
+            gate_closed.subscribe(move || {
+                notifier.notify_and_yield(NonZeroU32::new(2).unwrap());
+            })?;
+        }
+        gate_closed.enable_interrupt()?;
+    }
+
+    let mut last_status = gate_status();
+    loop {
+        let fast_window =
+            Duration::from_millis(config().post_command_moving_ms as u64) + FAST_CHECK;
+        let check_ms = if EspGateIo::MAIN.pulsed_within(fast_window) {
+            FAST_CHECK_MS
+        } else {
+            FALLBACK_CHECK_MS
+        };
+        if notification
+            .wait(TickType::new_millis(check_ms).into())
+            .is_some()
+        {
+            FreeRtos::delay_ms(SETTLE_MS);
+            // Interrupt is disabled after it fires, enable it for the next edge
+            hardware().gates[0]
+                .opened
+                .clone()
+                .lock()
+                .enable_interrupt()?;
+            hardware().gates[0]
+                .closed
+                .clone()
+                .lock()
+                .enable_interrupt()?;
+        }
+        let status = gate_status();
+        if status != last_status {
+            info!("Gate status changed from {} to {}", last_status, status);
+            last_status = status;
+            ws::broadcast_status();
+            mqtt::publish_status(status);
+            telegram::notify(status);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_svc::{hal::io::EspIOError, http::server::EspHttpConnection};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use crate::storage::{self, Namespace, Nvs};
+use crate::web::{form_field, html_escape, json_error, json_string, read_body};
+use crate::{config, mode::Mode};
+
+// Keys stored in the namespace: the settings below and the operating mode of mode.rs
+const NVS_KEYS: &[&str] = &[
+    "wifi_ssid",
+    "wifi_psk",
+    "gate_token",
+    "open_pulse_ms",
+    "sbs_pulse_ms",
+    "auto_close_secs",
+    "mode",
+];
+
+// Relay pulse range accepted by the relay and the gate controller
+pub const MIN_PULSE_MS: u32 = 50;
+pub const MAX_PULSE_MS: u32 = 2000;
+// Shorter auto-close could close the gate on a car still passing through, 0 - disabled
+pub const MIN_AUTO_CLOSE_SECS: u32 = 10;
+pub const MAX_AUTO_CLOSE_SECS: u32 = 3600;
+
+// Settings changeable at runtime: validated config() values overridden from NVS
+#[derive(Clone)]
+pub struct Settings {
+    pub wifi_ssid: String,
+    pub wifi_psk: String,
+    pub gate_token: String,
+    pub open_pulse_ms: u32,
+    pub sbs_pulse_ms: u32,
+    pub auto_close_secs: u32,
+}
+
+// Fields of a settings update, None - not given, the value is kept
+#[derive(Default)]
+struct Update {
+    wifi_ssid: Option<String>,
+    wifi_psk: Option<String>,
+    gate_token: Option<String>,
+    open_pulse_ms: Option<u32>,
+    sbs_pulse_ms: Option<u32>,
+    auto_close_secs: Option<u32>,
+}
+
+lazy_static! {
+    /// Effective settings
+    pub static ref SETTINGS: Arc<Mutex<Settings>> = Arc::new(Mutex::new(load()));
+}
+
+impl Settings {
+    // Settings as JSON, PSK and token are not disclosed
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"wifi_ssid\":{},\"wifi_psk\":{},\"gate_token\":{},\"open_pulse_ms\":{},\"sbs_pulse_ms\":{},\"auto_close_secs\":{}}}",
+            json_string(&self.wifi_ssid),
+            json_string(masked(&self.wifi_psk)),
+            json_string(masked(&self.gate_token)),
+            self.open_pulse_ms,
+            self.sbs_pulse_ms,
+            self.auto_close_secs
+        )
+    }
+}
+
+pub fn masked(secret: &str) -> &'static str {
+    if secret.is_empty() {
+        ""
+    } else {
+        "********"
+    }
+}
+
+// Copy of the effective settings
+pub fn current() -> Settings {
+    SETTINGS.clone().lock().clone()
+}
+
+// Effective WiFi SSID and PSK
+pub fn wifi_credentials() -> (String, String) {
+    let settings = SETTINGS.clone();
+    let settings = settings.lock();
+    (settings.wifi_ssid.clone(), settings.wifi_psk.clone())
+}
+
+fn open_nvs() -> anyhow::Result<Nvs> {
+    storage::open(Namespace::Settings)
+}
+
+fn load() -> Settings {
+    let mut settings = Settings {
+        wifi_ssid: config().wifi_ssid.to_string(),
+        wifi_psk: config().wifi_psk.to_string(),
+        gate_token: config().gate_token.to_string(),
+        open_pulse_ms: config().open_pulse_ms,
+        sbs_pulse_ms: config().sbs_pulse_ms,
+        auto_close_secs: config().auto_close_secs,
+    };
+    let nvs = match open_nvs() {
+        Ok(nvs) => nvs,
+        Err(e) => {
+            warn!("Can not open settings in NVS, using compiled values: {}", e);
+            return settings;
+        }
+    };
+    if let Some(wifi_ssid) = nvs.get_str("wifi_ssid") {
+        info!("wifi_ssid loaded from NVS");
+        settings.wifi_ssid = wifi_ssid;
+    }
+    if let Some(wifi_psk) = nvs.get_str("wifi_psk") {
+        info!("wifi_psk loaded from NVS");
+        settings.wifi_psk = wifi_psk;
+    }
+    if let Some(gate_token) = nvs.get_str("gate_token") {
+        info!("gate_token loaded from NVS");
+        settings.gate_token = gate_token;
+    }
+    for (key, value) in [
+        ("open_pulse_ms", &mut settings.open_pulse_ms),
+        ("sbs_pulse_ms", &mut settings.sbs_pulse_ms),
+        ("auto_close_secs", &mut settings.auto_close_secs),
+    ] {
+        if let Some(stored) = nvs.get_u32(key) {
+            info!("{} loaded from NVS", key);
+            *value = stored;
+        }
+    }
+    settings
+}
+
+// Values stored in NVS as JSON, keys which are not stored are absent. PSK and token are masked
+pub fn stored_json() -> String {
+    let nvs = match open_nvs() {
+        Ok(nvs) => nvs,
+        Err(e) => {
+            error!("Can not open settings in NVS: {}", e);
+            return "{}".to_string();
+        }
+    };
+    let mut fields = Vec::new();
+    for key in ["wifi_ssid", "wifi_psk", "gate_token"] {
+        if let Some(value) = nvs.get_str(key) {
+            let value = if key == "wifi_ssid" {
+                value.as_str()
+            } else {
+                masked(&value)
+            };
+            fields.push(format!("\"{}\":{}", key, json_string(value)));
+        }
+    }
+    for key in ["open_pulse_ms", "sbs_pulse_ms", "auto_close_secs"] {
+        if let Some(value) = nvs.get_u32(key) {
+            fields.push(format!("\"{}\":{}", key, value));
+        }
+    }
+    if let Some(mode) = nvs.get_u8("mode").and_then(Mode::from_u8) {
+        fields.push(format!("\"mode\":\"{}\"", mode.as_str()));
+    }
+    format!("{{{}}}", fields.join(","))
+}
+
+// Remove stored settings and mode, compiled values are used after reboot.
+// Returns the keys which were stored
+pub fn erase() -> anyhow::Result<Vec<&'static str>> {
+    let mut nvs = open_nvs()?;
+    let mut erased = Vec::new();
+    for &key in NVS_KEYS {
+        if nvs.remove(key)? {
+            info!("{} erased from NVS", key);
+            erased.push(key);
+        }
+    }
+    Ok(erased)
+}
+
+// Settings update from form fields wifi_ssid, wifi_psk, gate_token, open_pulse_ms,
+// sbs_pulse_ms and auto_close_secs, missing fields are kept
+pub fn handle_update(mut request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let body = read_body(&mut request, 512)?;
+    let update = match parse_update(&body).and_then(|update| validate(&update).map(|_| update)) {
+        Ok(update) => update,
+        Err(reason) => {
+            warn!("Settings update rejected: {}", reason);
+            return json_error(request, 400, "bad_request", Some(reason));
+        }
+    };
+    match save(update) {
+        Ok(()) => {
+            let json = SETTINGS.clone().lock().to_json();
+            let mut response = request.into_ok_response()?;
+            response.write_all(json.as_bytes())?;
+        }
+        Err(e) => {
+            error!("Can not save settings to NVS: {}", e);
+            json_error(request, 500, "nvs_error", Some("Can not save settings"))?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_update(body: &str) -> Result<Update, &'static str> {
+    let number = |name: &str, error: &'static str| -> Result<Option<u32>, &'static str> {
+        form_field(body, name)
+            .map(|value| value.trim().parse().map_err(|_| error))
+            .transpose()
+    };
+    Ok(Update {
+        wifi_ssid: form_field(body, "wifi_ssid"),
+        wifi_psk: form_field(body, "wifi_psk"),
+        gate_token: form_field(body, "gate_token"),
+        open_pulse_ms: number("open_pulse_ms", "open_pulse_ms must be a number")?,
+        sbs_pulse_ms: number("sbs_pulse_ms", "sbs_pulse_ms must be a number")?,
+        auto_close_secs: number("auto_close_secs", "auto_close_secs must be a number")?,
+    })
+}
+
+fn validate(update: &Update) -> Result<(), &'static str> {
+    if let Some(wifi_ssid) = &update.wifi_ssid {
+        if wifi_ssid.is_empty() || wifi_ssid.len() > 32 {
+            return Err("wifi_ssid must be 1..32 bytes");
+        }
+    }
+    if let Some(wifi_psk) = &update.wifi_psk {
+        if !wifi_psk.is_empty() && !(8..=64).contains(&wifi_psk.len()) {
+            return Err("wifi_psk must be empty or 8..64 bytes");
+        }
+    }
+    if let Some(gate_token) = &update.gate_token {
+        // Token is passed in a header and in the page URL, so it has to be plain text
+        if gate_token.len() > 64 || !gate_token.chars().all(|c| c.is_ascii_graphic()) {
+            return Err("gate_token must be up to 64 printable ASCII characters without spaces");
+        }
+    }
+    for pulse_ms in [update.open_pulse_ms, update.sbs_pulse_ms]
+        .into_iter()
+        .flatten()
+    {
+        if !(MIN_PULSE_MS..=MAX_PULSE_MS).contains(&pulse_ms) {
+            return Err("open_pulse_ms and sbs_pulse_ms must be 50..2000");
+        }
+    }
+    if update.auto_close_secs.is_some_and(|secs| {
+        secs != 0 && !(MIN_AUTO_CLOSE_SECS..=MAX_AUTO_CLOSE_SECS).contains(&secs)
+    }) {
+        return Err("auto_close_secs must be 0 or 10..3600");
+    }
+    Ok(())
+}
+
+// Store and apply WiFi credentials received by BLE provisioning
+pub fn save_wifi(wifi_ssid: &str, wifi_psk: &str) -> anyhow::Result<()> {
+    save(Update {
+        wifi_ssid: Some(wifi_ssid.to_string()),
+        wifi_psk: Some(wifi_psk.to_string()),
+        ..Default::default()
+    })
+}
+
+// Store given values to NVS and apply them. WiFi uses them on next reconnect,
+// the others on next use
+fn save(update: Update) -> anyhow::Result<()> {
+    let mut nvs = open_nvs()?;
+    let settings = SETTINGS.clone();
+    let mut settings = settings.lock();
+    // Fields are borrowed together below, which needs a plain reference rather than the guard
+    let settings = &mut *settings;
+    for (key, value, setting) in [
+        ("wifi_ssid", update.wifi_ssid, &mut settings.wifi_ssid),
+        ("wifi_psk", update.wifi_psk, &mut settings.wifi_psk),
+        ("gate_token", update.gate_token, &mut settings.gate_token),
+    ] {
+        if let Some(value) = value {
+            nvs.set_str(key, &value)?;
+            info!("{} saved to NVS", key);
+            *setting = value;
+        }
+    }
+    for (key, value, setting) in [
+        (
+            "open_pulse_ms",
+            update.open_pulse_ms,
+            &mut settings.open_pulse_ms,
+        ),
+        (
+            "sbs_pulse_ms",
+            update.sbs_pulse_ms,
+            &mut settings.sbs_pulse_ms,
+        ),
+        (
+            "auto_close_secs",
+            update.auto_close_secs,
+            &mut settings.auto_close_secs,
+        ),
+    ] {
+        if let Some(value) = value {
+            nvs.set_u32(key, value)?;
+            info!("{} saved to NVS", key);
+            *setting = value;
+        }
+    }
+    Ok(())
+}
+
+// Settings editor page, secrets are not shown: their fields are left empty to keep them
+pub fn page() -> String {
+    let settings = current();
+    let input = |label: &str, name: &str, kind: &str, value: &str| {
+        format!(
+            "<label>{}<input name=\"{}\" type=\"{}\" value=\"{}\"></label>",
+            label,
+            name,
+            kind,
+            html_escape(value)
+        )
+    };
+    [
+        include_str!("settings-0.html").to_string(),
+        input("SSID", "wifi_ssid", "text", &settings.wifi_ssid),
+        input("Пароль WiFi", "wifi_psk", "password", ""),
+        input("Токен", "gate_token", "password", ""),
+        input(
+            "Импульс открытия, мс",
+            "open_pulse_ms",
+            "number",
+            &settings.open_pulse_ms.to_string(),
+        ),
+        input(
+            "Импульс SBS, мс",
+            "sbs_pulse_ms",
+            "number",
+            &settings.sbs_pulse_ms.to_string(),
+        ),
+        input(
+            "Автозакрытие, с (0 - выключено)",
+            "auto_close_secs",
+            "number",
+            &settings.auto_close_secs.to_string(),
+        ),
+        include_str!("settings-1.html").to_string(),
+    ]
+    .concat()
+}
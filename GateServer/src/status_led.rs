@@ -0,0 +1,128 @@
+// Optional status LED on status_led_pin, a plain LED (active high) or a WS2812 with
+// status_led_ws2812. It blinks slowly while WiFi is disconnected, is solid while connected and
+// flashes on each relay pulse. Like the GateControl LED it is driven by its own task, so relay
+// pulses and request handlers are not delayed by the animation.
+use esp_idf_hal::{
+    delay::FreeRtos,
+    gpio::{AnyOutputPin, Output, PinDriver},
+};
+use lazy_static::lazy_static;
+use log::{error, info};
+use parking_lot::Mutex;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::StatusLedChannel,
+    config,
+    rgb_led::{RGB8, WS2812RMT},
+};
+
+// Blink phase is checked this often
+const TICK_MS: u32 = 50;
+// Half period of the blink while disconnected
+const SLOW_BLINK_MS: u128 = 500;
+// Half period of the plain LED blink during a flash
+const FAST_BLINK_MS: u128 = 100;
+// Flash length after a relay pulse
+const FLASH: Duration = Duration::from_millis(600);
+// WS2812 colors: disconnected, connected and relay flash
+const DISCONNECTED_COLOR: RGB8 = RGB8::new(50, 50, 0);
+const CONNECTED_COLOR: RGB8 = RGB8::new(0, 50, 0);
+const FLASH_COLOR: RGB8 = RGB8::new(50, 50, 50);
+
+enum Driver {
+    Plain(PinDriver<'static, AnyOutputPin, Output>),
+    Ws2812(WS2812RMT<'static>),
+}
+
+// LED with the last written state, so it is written only on a change
+struct Led {
+    driver: Driver,
+    shown: Option<(bool, RGB8)>,
+}
+
+impl Led {
+    fn write(&mut self, lit: bool, color: RGB8) {
+        if self.shown == Some((lit, color)) {
+            return;
+        }
+        let result = match &mut self.driver {
+            Driver::Plain(pin) if lit => pin.set_high().map_err(anyhow::Error::from),
+            Driver::Plain(pin) => pin.set_low().map_err(anyhow::Error::from),
+            Driver::Ws2812(ws2812) if lit => ws2812.set_pixel(color),
+            Driver::Ws2812(ws2812) => ws2812.set_pixel(RGB8::new(0, 0, 0)),
+        };
+        match result {
+            Ok(()) => self.shown = Some((lit, color)),
+            Err(e) => error!("Can not set status LED: {}", e),
+        }
+    }
+}
+
+static CONNECTED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    // Status LED, None - not configured or failed to set up
+    static ref LED: Arc<Mutex<Option<Led>>> = Arc::new(Mutex::new(None));
+    // Time of the last relay pulse, for the flash
+    static ref LAST_PULSE: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
+// Set up the LED driver on pin, channel is used by a WS2812 only
+pub fn init(pin: AnyOutputPin, channel: StatusLedChannel) -> anyhow::Result<()> {
+    let driver = if config().status_led_ws2812 {
+        Driver::Ws2812(WS2812RMT::new(pin, channel)?)
+    } else {
+        Driver::Plain(PinDriver::output(pin)?)
+    };
+    *LED.clone().lock() = Some(Led {
+        driver,
+        shown: None,
+    });
+    info!("Status LED on GPIO{}", config().status_led_pin);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    LED.clone().lock().is_some()
+}
+
+pub fn set_connected(connected: bool) {
+    CONNECTED.store(connected, Ordering::Relaxed);
+}
+
+// Start the flash, called on each relay pulse
+pub fn flash() {
+    *LAST_PULSE.clone().lock() = Some(Instant::now());
+}
+
+pub fn spawn_task() -> anyhow::Result<()> {
+    std::thread::Builder::new().stack_size(8192).spawn(|| {
+        let started = Instant::now();
+        loop {
+            FreeRtos::delay_ms(TICK_MS);
+            let elapsed_ms = started.elapsed().as_millis();
+            let flashing = LAST_PULSE
+                .clone()
+                .lock()
+                .is_some_and(|pulse| pulse.elapsed() < FLASH);
+            let (lit, color) = if flashing {
+                ((elapsed_ms / FAST_BLINK_MS) % 2 == 0, FLASH_COLOR)
+            } else if CONNECTED.load(Ordering::Relaxed) {
+                (true, CONNECTED_COLOR)
+            } else {
+                ((elapsed_ms / SLOW_BLINK_MS) % 2 == 0, DISCONNECTED_COLOR)
+            };
+            if let Some(led) = LED.clone().lock().as_mut() {
+                led.write(lit, color);
+            }
+        }
+    })?;
+    Ok(())
+}
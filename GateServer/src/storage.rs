@@ -0,0 +1,157 @@
+// NVS storage of all server data. Each kind of data has its own namespace named after
+// nvs_namespace: gate_cfg for settings and mode, gate_log for the access log, so another firmware
+// flashed to the same board keeps apart by a different nvs_namespace. nvs_partition names a data
+// partition of a custom partition table to use, empty - the default nvs partition shared with WiFi.
+// Getters log read errors and return None, as a missing value falls back to the compiled one.
+use anyhow::Context;
+use esp_idf_svc::{
+    nvs::{EspCustomNvs, EspCustomNvsPartition, EspDefaultNvs, EspNvs},
+    sys::EspError,
+};
+use lazy_static::lazy_static;
+use log::{error, info};
+
+use crate::{config, hardware};
+
+// NVS namespace is up to 15 characters, the longest suffix with "_" takes 4 of them
+pub const MAX_NAMESPACE_LEN: usize = 11;
+// Longest string value read back
+const MAX_STR_LEN: usize = 128;
+
+#[derive(Clone, Copy)]
+pub enum Namespace {
+    // Runtime settings and operating mode
+    Settings,
+    // Gate command access log
+    Log,
+}
+
+impl Namespace {
+    fn suffix(self) -> &'static str {
+        match self {
+            Namespace::Settings => "cfg",
+            Namespace::Log => "log",
+        }
+    }
+}
+
+enum Handle {
+    Default(EspDefaultNvs),
+    Custom(EspCustomNvs),
+}
+
+// Open namespace, closed on drop
+pub struct Nvs {
+    handle: Handle,
+    // Full namespace name for log messages
+    name: String,
+}
+
+lazy_static! {
+    /// nvs_partition, None - not configured or can not be initialized. A partition is initialized
+    /// once, so it is shared by all namespaces
+    static ref CUSTOM_PARTITION: Option<EspCustomNvsPartition> = take_custom_partition();
+}
+
+fn take_custom_partition() -> Option<EspCustomNvsPartition> {
+    let label = config().nvs_partition;
+    if label.is_empty() {
+        return None;
+    }
+    match EspCustomNvsPartition::take(label) {
+        Ok(partition) => {
+            info!("NVS partition {} initialized", label);
+            Some(partition)
+        }
+        Err(e) => {
+            error!("Can not initialize NVS partition {}: {}", label, e);
+            None
+        }
+    }
+}
+
+// Dispatch a call to the handle of either partition kind
+macro_rules! with_handle {
+    ($handle:expr, $nvs:ident => $call:expr) => {
+        match $handle {
+            Handle::Default($nvs) => $call,
+            Handle::Custom($nvs) => $call,
+        }
+    };
+}
+
+// Open a namespace for reading and writing, created if missing
+pub fn open(namespace: Namespace) -> anyhow::Result<Nvs> {
+    let name = format!("{}_{}", config().nvs_namespace, namespace.suffix());
+    let handle = if config().nvs_partition.is_empty() {
+        Handle::Default(EspNvs::new(hardware().nvs_partition.clone(), &name, true)?)
+    } else {
+        let partition = CUSTOM_PARTITION
+            .clone()
+            .with_context(|| format!("NVS partition {} unavailable", config().nvs_partition))?;
+        Handle::Custom(EspNvs::new(partition, &name, true)?)
+    };
+    Ok(Nvs { handle, name })
+}
+
+impl Nvs {
+    // String value, None - not stored (e.g. first boot) or unreadable
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        let mut buf = [0u8; MAX_STR_LEN];
+        let value = with_handle!(&self.handle, nvs => nvs.get_str(key, &mut buf));
+        self.read(key, value.map(|value| value.map(str::to_string)))
+    }
+
+    pub fn get_u8(&self, key: &str) -> Option<u8> {
+        self.read(key, with_handle!(&self.handle, nvs => nvs.get_u8(key)))
+    }
+
+    pub fn get_u32(&self, key: &str) -> Option<u32> {
+        self.read(key, with_handle!(&self.handle, nvs => nvs.get_u32(key)))
+    }
+
+    // Blob of up to buf length, None - not stored or unreadable
+    pub fn get_raw<'a>(&self, key: &str, buf: &'a mut [u8]) -> Option<&'a [u8]> {
+        self.read(
+            key,
+            with_handle!(&self.handle, nvs => nvs.get_raw(key, buf)),
+        )
+    }
+
+    pub fn set_str(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        let result = with_handle!(&mut self.handle, nvs => nvs.set_str(key, value));
+        self.written(key, result)
+    }
+
+    pub fn set_u8(&mut self, key: &str, value: u8) -> anyhow::Result<()> {
+        let result = with_handle!(&mut self.handle, nvs => nvs.set_u8(key, value));
+        self.written(key, result)
+    }
+
+    pub fn set_u32(&mut self, key: &str, value: u32) -> anyhow::Result<()> {
+        let result = with_handle!(&mut self.handle, nvs => nvs.set_u32(key, value));
+        self.written(key, result)
+    }
+
+    pub fn set_raw(&mut self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        let result = with_handle!(&mut self.handle, nvs => nvs.set_raw(key, value));
+        self.written(key, result.map(|_| ()))
+    }
+
+    // Remove a value, false - it was not stored
+    pub fn remove(&mut self, key: &str) -> anyhow::Result<bool> {
+        let result = with_handle!(&mut self.handle, nvs => nvs.remove(key));
+        result.with_context(|| format!("Can not remove {} from NVS {}", key, self.name))
+    }
+
+    fn read<T>(&self, key: &str, value: Result<Option<T>, EspError>) -> Option<T> {
+        value.unwrap_or_else(|e| {
+            error!("Can not read {} from NVS {}: {}", key, self.name, e);
+            None
+        })
+    }
+
+    fn written(&self, key: &str, result: Result<(), EspError>) -> anyhow::Result<()> {
+        result.with_context(|| format!("Can not write {} to NVS {}", key, self.name))
+    }
+}
@@ -0,0 +1,128 @@
+use embedded_svc::{http::client::Client, utils::io};
+use esp_idf_svc::{
+    http::client::{Configuration, EspHttpConnection},
+    sys,
+};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{config, gate_state::GateState, web::url_encode};
+
+// Messages are sent not more often, changes in between are merged into the last one
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
+const HTTP_TIMEOUT_MS: u64 = 10000;
+
+lazy_static! {
+    /// Channel to the notifier task, None - Telegram is not configured
+    static ref TELEGRAM_TASK: Arc<Mutex<Option<Sender<GateState>>>> = Arc::new(Mutex::new(None));
+}
+
+pub fn enabled() -> bool {
+    !config().telegram_token.is_empty() && !config().telegram_chat_id.is_empty()
+}
+
+// Notifier task, lives outside the WiFi reconnect loop.
+// Messages are sent by the task, so the sensor watcher never waits for the Bot API
+pub fn spawn_task() -> anyhow::Result<()> {
+    info!(
+        "Telegram notifications to chat {}",
+        config().telegram_chat_id
+    );
+    let (sender, receiver) = mpsc::channel();
+    *TELEGRAM_TASK.clone().lock() = Some(sender);
+    std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(move || {
+            // Created on the first message and reused, recreated after a failure
+            let mut client: Option<Client<EspHttpConnection>> = None;
+            let mut pending: Option<GateState> = None;
+            let mut last_sent: Option<(GateState, Instant)> = None;
+            loop {
+                let received = match (pending, last_sent) {
+                    (Some(_), Some((_, sent_at))) => {
+                        receiver.recv_timeout(MIN_INTERVAL.saturating_sub(sent_at.elapsed()))
+                    }
+                    (Some(_), None) => Err(RecvTimeoutError::Timeout),
+                    (None, _) => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match received {
+                    Ok(status) => {
+                        pending = Some(status);
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {}
+                }
+                let Some(status) = pending.take() else {
+                    continue;
+                };
+                if last_sent.is_some_and(|(sent, _)| sent == status) {
+                    continue;
+                }
+                if let Err(e) = send_message(&mut client, status) {
+                    error!("Telegram message failed: {}", e);
+                    client = None;
+                }
+                last_sent = Some((status, Instant::now()));
+            }
+        })?;
+    Ok(())
+}
+
+// Queue a gate state change message, if Telegram is configured
+pub fn notify(status: GateState) {
+    if let Some(sender) = TELEGRAM_TASK.clone().lock().as_ref() {
+        let _ = sender.send(status);
+    }
+}
+
+// Bot API sendMessage with the gate state as text
+fn send_message(
+    client: &mut Option<Client<EspHttpConnection>>,
+    status: GateState,
+) -> anyhow::Result<()> {
+    if client.is_none() {
+        *client = Some(Client::wrap(EspHttpConnection::new(&Configuration {
+            timeout: Some(Duration::from_millis(HTTP_TIMEOUT_MS)),
+            crt_bundle_attach: Some(sys::esp_crt_bundle_attach),
+            ..Default::default()
+        })?));
+    }
+    let client = client.as_mut().unwrap();
+    let text = format!("{}: {}", config().mdns_hostname, label(status));
+    let url = format!(
+        "https://api.telegram.org/bot{}/sendMessage?chat_id={}&text={}",
+        config().telegram_token,
+        url_encode(config().telegram_chat_id),
+        url_encode(&text)
+    );
+    let mut response = client.get(&url)?.submit()?;
+    let status_code = response.status();
+    // Body is read, so the connection can be reused
+    let mut buf = [0u8; 256];
+    let _ = io::try_read_full(&mut response, &mut buf).map_err(|e| e.0)?;
+    if status_code != 200 {
+        warn!("Telegram replied {} to {:?}", status_code, text);
+        anyhow::bail!("HTTP status {}", status_code);
+    }
+    info!("Telegram message {:?} sent", text);
+    Ok(())
+}
+
+// Same labels as on the main page
+fn label(status: GateState) -> &'static str {
+    match status {
+        GateState::Open => "Открыто",
+        GateState::Closed => "Закрыто",
+        GateState::Moving => "Промежуточное положение",
+        GateState::Fault => "Неисправность датчиков",
+    }
+}
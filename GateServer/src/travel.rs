@@ -0,0 +1,150 @@
+use esp_idf_hal::delay::FreeRtos;
+use lazy_static::lazy_static;
+use log::{error, info};
+use parking_lot::Mutex;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{config, gate_state::GateState, sensor_status, ws};
+
+struct Travel {
+    deadline: Instant,
+    // Status when the relay was pulsed
+    from: GateState,
+}
+
+// Gate reported moving after a relay pulse, see moving()
+struct Motion {
+    until: Instant,
+    // Status when the relay was pulsed
+    from: GateState,
+}
+
+// expected_travel_ms passed after a pulse without reaching a limit
+static STALLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// Travel being watched after a relay pulse
+    static ref TRAVEL: Arc<Mutex<Option<Travel>>> = Arc::new(Mutex::new(None));
+    /// Motion reported after a relay pulse
+    static ref MOTION: Arc<Mutex<Option<Motion>>> = Arc::new(Mutex::new(None));
+    /// Last travel error, cleared by the next successful travel
+    static ref TRAVEL_ERROR: Arc<Mutex<Option<&'static str>>> = Arc::new(Mutex::new(None));
+}
+
+// Watch the gate after a relay pulse: it is reported moving for expected_travel_ms unless a limit
+// is reached earlier, and it has to reach the other limit within gate_travel_timeout_secs.
+// Sensors are read directly, not the reported status, which is moving for post_command_moving_ms
+// after the pulse
+pub fn start() {
+    let expected_ms = config().expected_travel_ms;
+    let timeout_secs = config().gate_travel_timeout_secs;
+    if expected_ms == 0 && timeout_secs == 0 {
+        return;
+    }
+    let from = sensor_status();
+    if expected_ms > 0 {
+        *MOTION.clone().lock() = Some(Motion {
+            until: Instant::now() + Duration::from_millis(expected_ms as u64),
+            from,
+        });
+        STALLED.store(false, Ordering::Relaxed);
+    }
+    if timeout_secs == 0 {
+        return;
+    }
+    let travel = TRAVEL.clone();
+    *travel.lock() = Some(Travel {
+        deadline: Instant::now() + Duration::from_secs(timeout_secs as u64),
+        from,
+    });
+}
+
+// Last travel error for status reports, None - no error
+pub fn error() -> Option<&'static str> {
+    *TRAVEL_ERROR.clone().lock()
+}
+
+// Gate is moving by the last command: expected_travel_ms has not passed since the pulse and the
+// sensors are not at the other limit yet. sensors is the status from the limit sensors
+pub fn moving(sensors: GateState) -> bool {
+    let at_limit = matches!(sensors, GateState::Open | GateState::Closed);
+    if at_limit {
+        STALLED.store(false, Ordering::Relaxed);
+    }
+    let motion = MOTION.clone();
+    let mut motion = motion.lock();
+    let Some(watched) = motion.as_ref() else {
+        return false;
+    };
+    if at_limit && sensors != watched.from {
+        *motion = None;
+        return false;
+    }
+    if Instant::now() >= watched.until {
+        if sensors == GateState::Moving {
+            error!(
+                "Gate did not reach a limit in {} ms, stopped?",
+                config().expected_travel_ms
+            );
+            STALLED.store(true, Ordering::Relaxed);
+        }
+        *motion = None;
+        return false;
+    }
+    sensors != GateState::Fault
+}
+
+// Milliseconds left of expected_travel_ms, None - the gate is not moving by a command
+pub fn remaining_ms() -> Option<u64> {
+    let until = MOTION.clone().lock().as_ref()?.until;
+    Some(until.saturating_duration_since(Instant::now()).as_millis() as u64)
+}
+
+// Gate stopped between the limits after expected_travel_ms, until it reaches a limit or
+// the next command. Reported as a fault, though commands are accepted to move the gate on
+pub fn stalled() -> bool {
+    STALLED.load(Ordering::Relaxed)
+}
+
+// Travel watch task, lives outside the WiFi reconnect loop
+pub fn spawn_task() -> anyhow::Result<()> {
+    std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(|| loop {
+            FreeRtos::delay_ms(1000);
+            check();
+        })?;
+    Ok(())
+}
+
+fn check() {
+    // Expire the motion here too, so status clients learn about a stall without polling
+    let watching = MOTION.clone().lock().is_some();
+    if watching && !moving(sensor_status()) && stalled() {
+        ws::broadcast_status();
+    }
+    let travel = TRAVEL.clone();
+    let mut travel = travel.lock();
+    let Some(watched) = travel.as_ref() else {
+        return;
+    };
+    let status = sensor_status();
+    if matches!(status, GateState::Open | GateState::Closed) && status != watched.from {
+        info!("Gate reached {} limit", status);
+        *travel = None;
+        *TRAVEL_ERROR.clone().lock() = None;
+    } else if Instant::now() >= watched.deadline {
+        error!(
+            "Gate did not reach a limit in {} seconds, jammed?",
+            config().gate_travel_timeout_secs
+        );
+        *travel = None;
+        *TRAVEL_ERROR.clone().lock() = Some("timeout");
+    }
+}
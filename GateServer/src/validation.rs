@@ -0,0 +1,126 @@
+// Startup sanity check of the compiled config, so a typo in cfg.toml can not make the gate unsafe.
+// Out of range values are clamped with a warning, dangerous behaviors are disabled with an error.
+use core::fmt::Display;
+use log::{error, warn};
+
+use crate::response_format::{ResponseFormat, FORMATS};
+use crate::{log_level, settings, storage, Config};
+
+impl Config {
+    pub fn validate(mut self) -> Self {
+        self.open_pulse_ms = clamp(
+            "open_pulse_ms",
+            self.open_pulse_ms,
+            settings::MIN_PULSE_MS,
+            settings::MAX_PULSE_MS,
+        );
+        self.sbs_pulse_ms = clamp(
+            "sbs_pulse_ms",
+            self.sbs_pulse_ms,
+            settings::MIN_PULSE_MS,
+            settings::MAX_PULSE_MS,
+        );
+        if (1..settings::MIN_AUTO_CLOSE_SECS).contains(&self.auto_close_secs) {
+            error!(
+                "auto_close_secs {} may close the gate on a passing car, auto-close disabled",
+                self.auto_close_secs
+            );
+            self.auto_close_secs = 0;
+        }
+        self.auto_close_secs = clamp(
+            "auto_close_secs",
+            self.auto_close_secs,
+            0,
+            settings::MAX_AUTO_CLOSE_SECS,
+        );
+        // Each sample takes 10 ms
+        self.sensor_samples = clamp("sensor_samples", self.sensor_samples, 1, 15);
+        if self.watchdog_secs > 0 {
+            // Main loop feeds the watchdog once a second
+            self.watchdog_secs = clamp("watchdog_secs", self.watchdog_secs, 5, 3600);
+        }
+        if self.min_free_heap > 0 {
+            // Higher floors could be below the heap free right after start, a restart loop
+            self.min_free_heap = clamp("min_free_heap", self.min_free_heap, 4096, 65536);
+        }
+        if self.gate_travel_timeout_secs > 0 {
+            self.gate_travel_timeout_secs = clamp(
+                "gate_travel_timeout_secs",
+                self.gate_travel_timeout_secs,
+                5,
+                600,
+            );
+        }
+        if self.expected_travel_ms > 0 {
+            self.expected_travel_ms =
+                clamp("expected_travel_ms", self.expected_travel_ms, 1000, 600000);
+        }
+        self.warning_beep_ms = clamp("warning_beep_ms", self.warning_beep_ms, 0, 10000);
+        // Command handlers wait this long before the relay pulse
+        self.warning_delay_ms = clamp("warning_delay_ms", self.warning_delay_ms, 0, 10000);
+        self.post_command_moving_ms = clamp(
+            "post_command_moving_ms",
+            self.post_command_moving_ms,
+            0,
+            10000,
+        );
+        self.min_command_interval_ms = clamp(
+            "min_command_interval_ms",
+            self.min_command_interval_ms,
+            0,
+            60000,
+        );
+        self.client_window_secs = clamp("client_window_secs", self.client_window_secs, 1, 3600);
+        self.sbs_cooldown_ms = clamp("sbs_cooldown_ms", self.sbs_cooldown_ms, 0, 60000);
+        if self.http_port == 0 {
+            warn!("http_port 0 is out of range 1..65535, using 80");
+            self.http_port = 80;
+        }
+        // UTC-12:00 .. UTC+14:00
+        self.tz_offset_minutes = clamp("tz_offset_minutes", self.tz_offset_minutes, -720, 840);
+        if log_level::parse(self.log_level).is_none() {
+            warn!(
+                "log_level {:?} is not one of {}, using info",
+                self.log_level,
+                log_level::LEVELS.join(", ")
+            );
+            self.log_level = "info";
+        }
+        if ResponseFormat::parse(self.response_format).is_none() {
+            warn!(
+                "response_format {:?} is not one of {}, using compact",
+                self.response_format,
+                FORMATS.join(", ")
+            );
+            self.response_format = "compact";
+        }
+        let namespace_ok = (1..=storage::MAX_NAMESPACE_LEN).contains(&self.nvs_namespace.len())
+            && self
+                .nvs_namespace
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !namespace_ok {
+            // Another namespace would lose the stored settings, so this is an error
+            error!(
+                "nvs_namespace {:?} must be 1..{} letters, digits or _, using gate",
+                self.nvs_namespace,
+                storage::MAX_NAMESPACE_LEN
+            );
+            self.nvs_namespace = "gate";
+        }
+        self
+    }
+}
+
+// Value limited to min..=max, a change is logged. Values which do not compare (NaN) become min
+fn clamp<T: PartialOrd + Copy + Display>(name: &str, value: T, min: T, max: T) -> T {
+    if value >= min && value <= max {
+        return value;
+    }
+    let clamped = if value > max { max } else { min };
+    warn!(
+        "{} {} is out of range {}..{}, using {}",
+        name, value, min, max, clamped
+    );
+    clamped
+}
@@ -0,0 +1,163 @@
+use embedded_svc::{http::server::Request, io::Write, utils::io};
+use esp_idf_svc::{hal::io::EspIOError, handle::RawHandle, http::server::EspHttpConnection, sys};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub use gate_logic::http::{form_field, url_decode, url_encode};
+
+use crate::cors;
+
+// Headers of the HTML pages, explicit charset keeps the Cyrillic labels readable on all browsers
+pub const HTML_HEADERS: &[(&str, &str)] = &[("Content-Type", "text/html; charset=utf-8")];
+
+// 16x16 gate icon, served so browsers do not log 404 for /favicon.ico
+const FAVICON: &[u8] = include_bytes!("favicon.ico");
+
+pub fn favicon(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let mut response = request.into_response(
+        200,
+        Some("OK"),
+        &[
+            ("Content-Type", "image/x-icon"),
+            ("Cache-Control", "max-age=86400"),
+        ],
+    )?;
+    response.write_all(FAVICON)?;
+    Ok(())
+}
+
+// 405 response for a command sent with a method it does not accept
+pub fn method_not_allowed(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    json_error_with_headers(
+        request,
+        405,
+        "method_not_allowed",
+        None,
+        &[("Allow", "POST")],
+    )
+}
+
+// Error response with JSON body like {"error":"rate_limited"} or, with a message for people,
+// {"error":"bad_request","message":"mode must be normal, hold_open or locked"}.
+// error is a fixed snake_case code clients can match on
+pub fn json_error(
+    request: Request<&mut EspHttpConnection>,
+    status: u16,
+    error: &str,
+    message: Option<&str>,
+) -> Result<(), EspIOError> {
+    json_error_with_headers(request, status, error, message, &[])
+}
+
+// json_error() with headers besides Content-Type and CORS
+pub fn json_error_with_headers(
+    request: Request<&mut EspHttpConnection>,
+    status: u16,
+    error: &str,
+    message: Option<&str>,
+    headers: &[(&str, &str)],
+) -> Result<(), EspIOError> {
+    let mut all_headers = vec![("Content-Type", "application/json")];
+    all_headers.extend_from_slice(cors::headers());
+    all_headers.extend_from_slice(headers);
+    let body = match message {
+        Some(message) => format!(
+            "{{\"error\":{},\"message\":{}}}",
+            json_string(error),
+            json_string(message)
+        ),
+        None => format!("{{\"error\":{}}}", json_string(error)),
+    };
+    let mut response = request.into_response(status, Some(reason_phrase(status)), &all_headers)?;
+    response.write_all(body.as_bytes())?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        429 => "Too Many Requests",
+        _ => "Internal Server Error",
+    }
+}
+
+// Read small request body (form or JSON) into a string, truncated to buffer size
+pub fn read_body(
+    request: &mut Request<&mut EspHttpConnection>,
+    max_len: usize,
+) -> Result<String, EspIOError> {
+    let mut buf = vec![0u8; max_len];
+    let bytes_read = io::try_read_full(request, &mut buf).map_err(|e| e.0)?;
+    Ok(String::from_utf8_lossy(&buf[..bytes_read]).into_owned())
+}
+
+// String value of a field from a small flat JSON object like {"cmd":"open"}.
+// Escapes in the value are not supported
+pub fn json_str_field<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("\"{}\"", name);
+    let rest = &body[body.find(&key)? + key.len()..];
+    let rest = rest
+        .trim_start()
+        .strip_prefix(':')?
+        .trim_start()
+        .strip_prefix('"')?;
+    Some(&rest[..rest.find('"')?])
+}
+
+// JSON string literal with escaped quotes, backslashes and control characters
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Text escaped for HTML element content and attribute values
+pub fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Address of the client which sent the request, IPv4 clients are reported as IPv4
+pub fn peer_ip(request: &mut Request<&mut EspHttpConnection>) -> Option<IpAddr> {
+    let raw_request = request.connection().raw_connection().ok()?.handle();
+    // HTTP server listens on an IPv6 socket, IPv4 clients have IPv4-mapped addresses
+    let mut addr: sys::sockaddr_in6 = unsafe { core::mem::zeroed() };
+    let mut len = core::mem::size_of::<sys::sockaddr_in6>() as sys::socklen_t;
+    let result = unsafe {
+        let fd = sys::httpd_req_to_sockfd(raw_request);
+        sys::lwip_getpeername(fd, &mut addr as *mut _ as *mut sys::sockaddr, &mut len)
+    };
+    if result != 0 {
+        return None;
+    }
+    if addr.sin6_family as u32 == sys::AF_INET {
+        let addr = unsafe { &*(&addr as *const _ as *const sys::sockaddr_in) };
+        // s_addr is in network byte order, so its memory bytes are the octets
+        let octets = addr.sin_addr.s_addr.to_ne_bytes();
+        return Some(IpAddr::V4(Ipv4Addr::from(octets)));
+    }
+    let ip = Ipv6Addr::from(unsafe { addr.sin6_addr.un.u8_addr });
+    Some(ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4))
+}
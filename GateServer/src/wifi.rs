@@ -1,28 +1,31 @@
 use esp_idf_hal::{delay::FreeRtos, peripheral::Peripheral};
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
-    nvs::*,
-    wifi::{AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi},
+    netif::{EspNetif, NetifStack},
+    sys::{esp, esp_wifi_sta_get_ap_info, wifi_ap_record_t},
+    wifi::{BlockingWifi, ClientConfiguration, Configuration, EspWifi, WifiDriver},
 };
+use log::warn;
 
-use crate::PERIPHERALS;
+use crate::{config, hardware, ipv6, wifi_setup};
 
 pub fn connect_wifi(wifi_ssid: &str, wifi_psk: &str) -> anyhow::Result<Box<EspWifi<'static>>> {
     use log::info;
 
-    let auth_method = if wifi_psk.is_empty() {
-        info!("Wifi password is empty");
-        AuthMethod::None
-    } else {
-        AuthMethod::WPA2Personal
-    };
+    let auth_method = wifi_setup::auth_method(config().auth_method, wifi_psk);
 
-    let _nvs_default_partition: EspNvsPartition<NvsDefault> = EspDefaultNvsPartition::take()?;
-    let peripherals = PERIPHERALS.clone();
+    let _nvs_default_partition = hardware().nvs_partition.clone();
+    let peripherals = hardware().peripherals.clone();
     let mut peripherals = peripherals.lock();
     let modem = unsafe { peripherals.modem.clone_unchecked() };
     let sysloop = EspSystemEventLoop::take()?;
-    let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), None)?;
+    let driver = WifiDriver::new(modem, sysloop.clone(), None)?;
+    let sta_netif = wifi_setup::sta_netif(wifi_setup::static_ip_settings(
+        config().static_ip,
+        config().gateway,
+        config().netmask,
+    ))?;
+    let mut esp_wifi = EspWifi::wrap_all(driver, sta_netif, EspNetif::new(NetifStack::Ap)?)?;
     let mut wifi = BlockingWifi::wrap(&mut esp_wifi, sysloop)?;
     wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
     wifi.start()?;
@@ -68,6 +71,17 @@ pub fn connect_wifi(wifi_ssid: &str, wifi_psk: &str) -> anyhow::Result<Box<EspWi
         info!("Get IP info");
         let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
         info!("Wifi DHCP info: {:?}", ip_info);
+        // IPv4 is up, so the connection is usable even if IPv6 fails
+        if let Err(e) = ipv6::enable(wifi.wifi().sta_netif()) {
+            warn!("Can not enable IPv6: {}", e);
+        }
         break 'wifi_loop Ok(Box::new(esp_wifi));
     }
 }
+
+// RSSI of connected access point, None - not connected
+pub fn current_rssi() -> Option<i8> {
+    let mut ap_info = wifi_ap_record_t::default();
+    esp!(unsafe { esp_wifi_sta_get_ap_info(&mut ap_info) }).ok()?;
+    Some(ap_info.rssi)
+}
@@ -0,0 +1,119 @@
+// Scan of nearby access points for installation diagnostics: GET /wifi/scan returns
+// [{"ssid":"Home","rssi":-52,"channel":6,"auth":"wpa2"},...], strongest first, to see interference
+// and pick a channel. The radio leaves the connected channel while scanning, so the connection
+// stalls for a few seconds and scans are limited to one per SCAN_INTERVAL. If the connection is
+// lost meanwhile, the main loop reconnects as after any WiFi drop.
+use embedded_svc::{http::server::Request, io::Write};
+use esp_idf_svc::{
+    hal::io::EspIOError,
+    http::server::EspHttpConnection,
+    sys::{
+        esp, esp_wifi_scan_get_ap_num, esp_wifi_scan_get_ap_records, esp_wifi_scan_start,
+        wifi_ap_record_t, wifi_auth_mode_t, wifi_auth_mode_t_WIFI_AUTH_OPEN,
+        wifi_auth_mode_t_WIFI_AUTH_WEP, wifi_auth_mode_t_WIFI_AUTH_WPA2_ENTERPRISE,
+        wifi_auth_mode_t_WIFI_AUTH_WPA2_PSK, wifi_auth_mode_t_WIFI_AUTH_WPA2_WPA3_PSK,
+        wifi_auth_mode_t_WIFI_AUTH_WPA3_PSK, wifi_auth_mode_t_WIFI_AUTH_WPA_PSK,
+        wifi_auth_mode_t_WIFI_AUTH_WPA_WPA2_PSK, wifi_scan_config_t,
+    },
+};
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::cors;
+use crate::web::{json_error, json_error_with_headers, json_string};
+
+// Minimal interval between scans
+const SCAN_INTERVAL: Duration = Duration::from_secs(30);
+// Most access points reported, keeps the reply small in a busy area
+const MAX_APS: u16 = 20;
+
+lazy_static! {
+    static ref LAST_SCAN: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+}
+
+fn auth_name(auth: wifi_auth_mode_t) -> &'static str {
+    match auth {
+        wifi_auth_mode_t_WIFI_AUTH_OPEN => "open",
+        wifi_auth_mode_t_WIFI_AUTH_WEP => "wep",
+        wifi_auth_mode_t_WIFI_AUTH_WPA_PSK => "wpa",
+        wifi_auth_mode_t_WIFI_AUTH_WPA2_PSK => "wpa2",
+        wifi_auth_mode_t_WIFI_AUTH_WPA_WPA2_PSK => "wpa_wpa2",
+        wifi_auth_mode_t_WIFI_AUTH_WPA2_ENTERPRISE => "wpa2_enterprise",
+        wifi_auth_mode_t_WIFI_AUTH_WPA3_PSK => "wpa3",
+        wifi_auth_mode_t_WIFI_AUTH_WPA2_WPA3_PSK => "wpa2_wpa3",
+        _ => "other",
+    }
+}
+
+// Blocking scan of all channels, hidden networks are skipped
+fn scan() -> anyhow::Result<Vec<wifi_ap_record_t>> {
+    let scan_config = wifi_scan_config_t::default();
+    esp!(unsafe { esp_wifi_scan_start(&scan_config, true) })?;
+    let mut found: u16 = 0;
+    esp!(unsafe { esp_wifi_scan_get_ap_num(&mut found) })?;
+    // Records beyond count are freed by the call
+    let mut count = found.min(MAX_APS);
+    let mut records = vec![wifi_ap_record_t::default(); count as usize];
+    esp!(unsafe { esp_wifi_scan_get_ap_records(&mut count, records.as_mut_ptr()) })?;
+    records.truncate(count as usize);
+    records.sort_by(|a, b| b.rssi.cmp(&a.rssi));
+    info!("WiFi scan found {} access points", found);
+    Ok(records)
+}
+
+fn json(records: &[wifi_ap_record_t]) -> String {
+    let aps: Vec<String> = records
+        .iter()
+        .map(|record| {
+            let len = record
+                .ssid
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(record.ssid.len());
+            format!(
+                "{{\"ssid\":{},\"rssi\":{},\"channel\":{},\"auth\":\"{}\"}}",
+                json_string(&String::from_utf8_lossy(&record.ssid[..len])),
+                record.rssi,
+                record.primary,
+                auth_name(record.authmode)
+            )
+        })
+        .collect();
+    format!("[{}]", aps.join(","))
+}
+
+pub fn handle(request: Request<&mut EspHttpConnection>) -> Result<(), EspIOError> {
+    let last_scan = LAST_SCAN.clone();
+    let mut last_scan = last_scan.lock();
+    if let Some(wait) = last_scan.and_then(|last| SCAN_INTERVAL.checked_sub(last.elapsed())) {
+        warn!(
+            "WiFi scan rejected: previous scan was less than {:?} ago",
+            SCAN_INTERVAL
+        );
+        let retry_after = (wait.as_secs() + 1).to_string();
+        return json_error_with_headers(
+            request,
+            429,
+            "rate_limited",
+            Some("One scan per 30 seconds"),
+            &[("Retry-After", &retry_after)],
+        );
+    }
+    *last_scan = Some(Instant::now());
+    match scan() {
+        Ok(records) => {
+            let mut response = request.into_response(200, Some("OK"), cors::headers())?;
+            response.write_all(json(&records).as_bytes())?;
+            Ok(())
+        }
+        Err(e) => {
+            error!("WiFi scan failed: {}", e);
+            json_error(request, 500, "scan_failed", Some("WiFi scan failed"))
+        }
+    }
+}
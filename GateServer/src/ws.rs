@@ -0,0 +1,76 @@
+use embedded_svc::ws::FrameType;
+use esp_idf_svc::{
+    http::server::ws::{EspHttpWsConnection, EspHttpWsDetachedSender},
+    sys::{EspError, ESP_ERR_INVALID_SIZE},
+};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use crate::gate_json_status;
+
+lazy_static! {
+    /// Open WebSocket sessions receiving gate status
+    static ref SUBSCRIBERS: Arc<Mutex<Vec<EspHttpWsDetachedSender>>> =
+        Arc::new(Mutex::new(Vec::new()));
+    /// Held while sending, so senders are not used after the server is dropped
+    static ref SENDING: Arc<Mutex<()>> = Arc::new(Mutex::new(()));
+}
+
+// WebSocket /ws handler: send current status to a new session, forget closed ones
+pub fn handle(ws: &mut EspHttpWsConnection) -> Result<(), EspError> {
+    if ws.is_new() {
+        ws.send(FrameType::Text(false), gate_json_status().as_bytes())?;
+        let sender = ws.create_detached_sender()?;
+        let subscribers = SUBSCRIBERS.clone();
+        let mut subscribers = subscribers.lock();
+        subscribers.push(sender);
+        info!("WebSocket session opened ({} open)", subscribers.len());
+    } else if ws.is_closed() {
+        let session = ws.session();
+        let subscribers = SUBSCRIBERS.clone();
+        let mut subscribers = subscribers.lock();
+        subscribers.retain(|sender| sender.session() != session);
+        info!("WebSocket session closed ({} open)", subscribers.len());
+    } else {
+        // Incoming frames are not used, read and drop them
+        let (_, len) = ws.recv(&mut [])?;
+        if len > 128 {
+            ws.send(FrameType::Close, &[])?;
+            return Err(EspError::from_infallible::<ESP_ERR_INVALID_SIZE>());
+        }
+        let mut buf = [0u8; 128];
+        ws.recv(&mut buf[..len])?;
+    }
+    Ok(())
+}
+
+// Forget all sessions, called before the HTTP server is dropped
+pub fn close_all() {
+    let sending = SENDING.clone();
+    let _sending = sending.lock();
+    SUBSCRIBERS.clone().lock().clear();
+}
+
+// Send current gate status to all sessions, called on sensor changes
+pub fn broadcast_status() {
+    broadcast(&gate_json_status());
+}
+
+fn broadcast(json: &str) {
+    let sending = SENDING.clone();
+    let _sending = sending.lock();
+    // Senders are cloned, so the handler is not blocked on the list while sending
+    let senders = SUBSCRIBERS.clone().lock().clone();
+    for mut sender in senders {
+        if let Err(e) = sender.send(FrameType::Text(false), json.as_bytes()) {
+            let session = sender.session();
+            warn!("WebSocket send to session {} failed: {}", session, e);
+            SUBSCRIBERS
+                .clone()
+                .lock()
+                .retain(|sender| sender.session() != session);
+        }
+    }
+}
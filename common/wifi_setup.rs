@@ -0,0 +1,80 @@
+// WiFi station setup shared by GateServer and GateControl: auth method, static IP and the
+// station interface. Included with #[path] by both crates, so it must not depend on crate items:
+// config values are passed in by each crate's wifi module.
+use esp_idf_svc::{
+    ipv4::{self, ClientSettings, Mask, Subnet},
+    netif::{EspNetif, NetifConfiguration, NetifStack},
+    wifi::AuthMethod,
+};
+use log::{info, warn};
+use std::net::Ipv4Addr;
+
+// Auth method by auth_method config: wpa2, wpa3, wpa2wpa3 or none.
+// Empty - by password: no authentication without it, WPA2 with it
+pub fn auth_method(auth_method: &str, wifi_psk: &str) -> AuthMethod {
+    match auth_method {
+        "wpa2" => AuthMethod::WPA2Personal,
+        "wpa3" => AuthMethod::WPA3Personal,
+        "wpa2wpa3" => AuthMethod::WPA2WPA3Personal,
+        "none" => AuthMethod::None,
+        auth_method => {
+            if !auth_method.is_empty() {
+                warn!("Unknown auth_method {:?}, chosen by password", auth_method);
+            }
+            if wifi_psk.is_empty() {
+                info!("Wifi password is empty");
+                AuthMethod::None
+            } else {
+                AuthMethod::WPA2Personal
+            }
+        }
+    }
+}
+
+// Static IP settings by static_ip, gateway and netmask config, None - use DHCP
+pub fn static_ip_settings(static_ip: &str, gateway: &str, netmask: &str) -> Option<ClientSettings> {
+    if static_ip.is_empty() {
+        return None;
+    }
+    let ip = static_ip.parse::<Ipv4Addr>();
+    let gateway_ip = gateway.parse::<Ipv4Addr>();
+    let mask = netmask
+        .parse::<Ipv4Addr>()
+        .ok()
+        .and_then(|mask| Mask::try_from(mask).ok());
+    match (ip, gateway_ip, mask) {
+        (Ok(ip), Ok(gateway), Some(mask)) => Some(ClientSettings {
+            ip,
+            subnet: Subnet { gateway, mask },
+            dns: Some(gateway),
+            secondary_dns: None,
+        }),
+        _ => {
+            warn!(
+                "Invalid static IP configuration ip={} gateway={} netmask={}, using DHCP",
+                static_ip, gateway, netmask
+            );
+            None
+        }
+    }
+}
+
+// Station interface, with fixed settings or DHCP
+pub fn sta_netif(static_ip: Option<ClientSettings>) -> anyhow::Result<EspNetif> {
+    let netif = match static_ip {
+        Some(settings) => {
+            info!(
+                "Using static IP {} gateway {}/{}",
+                settings.ip, settings.subnet.gateway, settings.subnet.mask
+            );
+            EspNetif::new_with_conf(&NetifConfiguration {
+                ip_configuration: ipv4::Configuration::Client(ipv4::ClientConfiguration::Fixed(
+                    settings,
+                )),
+                ..NetifConfiguration::wifi_default_client()
+            })?
+        }
+        None => EspNetif::new(NetifStack::Sta)?,
+    };
+    Ok(netif)
+}